@@ -25,9 +25,15 @@ struct PostProcessSettings {
     colours: array<vec3<f32>, 4>,
     darkness: i32,
 
+    // Screen transitions that cover the screen up, rather than just changing the palette.
+    // 0 is none, 1 is a wipe from the left, 2 is an iris closing in on the middle, 3 is a mosaic.
+    transition_style: i32,
+    // How far through the transition we are, 0 is clear and 1 is completely covered.
+    transition_amount: f32,
+
 #ifdef SIXTEEN_BYTE_ALIGNMENT
     // WebGL2 structs must be 16 byte aligned.
-    _webgl2_padding: vec3<f32>
+    _webgl2_padding: f32
 #endif
 }
 @group(0) @binding(2) var<uniform> settings: PostProcessSettings;
@@ -38,10 +44,45 @@ fn get_palette_colour(index: i32) -> vec3<f32> {
     return settings.colours[darkness_mod];
 }
 
+// The size of the game screen in pixels, the transitions are worked out in these rather than screen pixels.
+const SCREEN_SIZE: vec2<f32> = vec2<f32>(160.0, 144.0);
+
+// The biggest mosaic blocks, in game pixels.
+const MOSAIC_MAX_BLOCK: f32 = 16.0;
+
+// Is this bit of the screen covered up by a wipe or iris?
+fn covered(uv: vec2<f32>) -> bool {
+    let amount = settings.transition_amount;
+    if settings.transition_style == 1 {
+        return uv.x < amount;
+    } else if settings.transition_style == 2 {
+        // The circle starts big enough to reach the corners, and shrinks down to nothing.
+        let offset = (uv - vec2<f32>(0.5, 0.5)) * SCREEN_SIZE;
+        let radius = length(SCREEN_SIZE * 0.5) * (1.0 - amount);
+        return length(offset) > radius;
+    }
+    return false;
+}
+
+// Where to read the screen from. The mosaic reads the middle of each block.
+fn sample_uv(uv: vec2<f32>) -> vec2<f32> {
+    if settings.transition_style == 3 {
+        let block = mix(1.0, MOSAIC_MAX_BLOCK, settings.transition_amount);
+        let blocks = SCREEN_SIZE / floor(block);
+        return (floor(uv * blocks) + vec2<f32>(0.5, 0.5)) / blocks;
+    }
+    return uv;
+}
+
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    // Covered up bits are always the darkest colour.
+    if covered(in.uv) {
+        return vec4<f32>(settings.colours[3], 1.0);
+    }
+
     // Determine which palette colour we are going to use
-    let colour_r = textureSample(screen_texture, texture_sampler, in.uv).r;
+    let colour_r = textureSample(screen_texture, texture_sampler, sample_uv(in.uv)).r;
     if colour_r > 0.75 {
        return vec4<f32>(get_palette_colour(0), 1.0);
     } else if colour_r > 0.3 {
@@ -0,0 +1,145 @@
+// Compare rebuilding the BlockedTilesCache from scratch every tick against keeping it up to date
+// as blocking things come and go, on a made up 256x256 level.
+// This only measures Blocking entities. Walls are terrain now (see the TerrainCache), so real levels have far
+// fewer Blocking things than this one, which blocks about a quarter of its tiles to give the cache some work.
+
+use std::{collections::HashSet, time::{Duration, Instant}};
+
+use bevy::{ecs::schedule::Schedule, prelude::*};
+use bevy_ecs_ldtk::{LevelEvent, LevelIid};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use bevy_plat::collision::{self, BlockedTilesCache, Blocking, WorldGridCoords};
+
+const LEVEL_SIZE: i32 = 256;
+const LEVEL_IID: &str = "synthetic-level";
+
+// Walls all the way round the edge and roughly a quarter of the inside blocked, like a maze-ish level.
+fn is_blocked(x: i32, y: i32) -> bool {
+    x == 0 || y == 0 || x == LEVEL_SIZE - 1 || y == LEVEL_SIZE - 1 || (x * 7 + y * 13) % 4 == 0
+}
+
+// The cache the game used to rebuild every tick, just a set of the blocked tiles.
+#[derive(Default, Resource)]
+struct FullRebuildCache {
+    blocked_tile_locations: HashSet<WorldGridCoords>
+}
+
+// Rebuild the whole cache from scratch from every blocking entity, the same way the game used to.
+fn build_blocked_tile_cache(mut full_rebuild_cache: ResMut<FullRebuildCache>,
+                            blocked_tiles: Query<&WorldGridCoords, With<Blocking>>) {
+    let mut blocked_tile_locations = HashSet::new();
+    for world_grid_coords in blocked_tiles.iter() {
+        blocked_tile_locations.insert(*world_grid_coords);
+    }
+
+    *full_rebuild_cache = FullRebuildCache { blocked_tile_locations };
+}
+
+// Spawn the level the same way ldtk does, level -> layer -> tiles, so the cache can tell which level each tile is from.
+fn spawn_synthetic_level(world: &mut World) -> Entity {
+    world.spawn(LevelIid::new(LEVEL_IID)).with_children(|level| {
+        level.spawn_empty().with_children(|layer| {
+            for x in 0..LEVEL_SIZE {
+                for y in 0..LEVEL_SIZE {
+                    if is_blocked(x, y) {
+                        layer.spawn((WorldGridCoords { x, y, z: 0 }, Blocking));
+                    }
+                }
+            }
+        });
+    }).id()
+}
+
+fn synthetic_level() -> World {
+    let mut world = World::new();
+    world.init_resource::<BlockedTilesCache>();
+    world.init_resource::<Events<LevelEvent>>();
+    spawn_synthetic_level(&mut world);
+    world
+}
+
+fn incremental_schedule() -> Schedule {
+    let mut schedule = Schedule::default();
+    schedule.add_systems((collision::evict_despawned_level_blocked_tiles,
+                          collision::remove_blocked_tiles,
+                          collision::add_blocked_tiles).chain());
+    schedule
+}
+
+fn run_tick(schedule: &mut Schedule, world: &mut World) {
+    schedule.run(world);
+    world.clear_trackers();
+    world.resource_mut::<Events<LevelEvent>>().update();
+}
+
+fn blocked_tiles_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("blocked_tiles_cache_256x256");
+
+    // What we used to do, every single tick.
+    group.bench_function("full_rebuild", |b| {
+        let mut world = synthetic_level();
+        world.init_resource::<FullRebuildCache>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(build_blocked_tile_cache);
+
+        b.iter(|| run_tick(&mut schedule, &mut world));
+
+        // Make sure it actually found every blocked tile.
+        let blocked_tile_count = (0..LEVEL_SIZE).flat_map(|x| (0..LEVEL_SIZE).map(move |y| (x, y))).filter(|&(x, y)| is_blocked(x, y)).count();
+        assert_eq!(world.resource::<FullRebuildCache>().blocked_tile_locations.len(), blocked_tile_count);
+    });
+
+    // A tick where nothing was spawned or despawned, which is almost all of them.
+    group.bench_function("incremental_no_changes", |b| {
+        let mut world = synthetic_level();
+        let mut schedule = incremental_schedule();
+        run_tick(&mut schedule, &mut world);
+
+        b.iter(|| run_tick(&mut schedule, &mut world));
+    });
+
+    // A tick where one blocking thing went away and another one turned up.
+    group.bench_function("incremental_one_change", |b| {
+        let mut world = synthetic_level();
+        let mut schedule = incremental_schedule();
+        run_tick(&mut schedule, &mut world);
+
+        let mut entity = world.spawn((WorldGridCoords { x: 1, y: 1, z: 0 }, Blocking)).id();
+        run_tick(&mut schedule, &mut world);
+
+        b.iter(|| {
+            world.despawn(entity);
+            entity = world.spawn((WorldGridCoords { x: 1, y: 1, z: 0 }, Blocking)).id();
+            run_tick(&mut schedule, &mut world);
+        });
+    });
+
+    // The tick a whole level goes away (LevelEvent::Despawned), only the despawn tick is timed.
+    group.bench_function("incremental_level_despawned", |b| {
+        let mut world = World::new();
+        world.init_resource::<BlockedTilesCache>();
+        world.init_resource::<Events<LevelEvent>>();
+        let mut schedule = incremental_schedule();
+
+        b.iter_custom(|iterations| {
+            let mut elapsed = Duration::ZERO;
+            for _ in 0..iterations {
+                let level = spawn_synthetic_level(&mut world);
+                run_tick(&mut schedule, &mut world);
+
+                let start = Instant::now();
+                world.entity_mut(level).despawn_recursive();
+                world.send_event(LevelEvent::Despawned(LevelIid::new(LEVEL_IID)));
+                run_tick(&mut schedule, &mut world);
+                elapsed += start.elapsed();
+            }
+            elapsed
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, blocked_tiles_cache);
+criterion_main!(benches);
@@ -1,9 +1,9 @@
 use std::time::Duration;
 
-use bevy::{app::{App, FixedUpdate, Plugin, Update}, asset::{AssetServer, Assets, Handle}, audio::{AudioSink, AudioSinkPlayback, AudioSource, AudioSourceBundle, PlaybackMode, PlaybackSettings}, prelude::{Added, Bundle, Commands, Component, Entity, Event, EventReader, EventWriter, Image, IntoSystemConfigs, Query, Res, With}, scene::ron::de, sprite::TextureAtlasLayout, time::Time};
-use bevy_ecs_ldtk::{app::LdtkEntityAppExt, assets::{LdtkProject, LevelMetadataAccessor}, ldtk::{LayerInstance, TilesetDefinition}, prelude::{LdtkEntity, LdtkFields}, EntityIid, EntityInstance};
+use bevy::{app::{App, FixedUpdate, Plugin, Update}, asset::{AssetServer, Handle}, audio::{AudioSink, AudioSinkPlayback, AudioSource, AudioSourceBundle, PlaybackMode, PlaybackSettings, Volume}, prelude::{Added, Bundle, Commands, Component, DetectChanges, Entity, Event, EventReader, EventWriter, Image, IntoSystemConfigs, Query, Res, ResMut, Resource, With, Without}, scene::ron::de, sprite::TextureAtlasLayout, time::Time};
+use bevy_ecs_ldtk::{app::LdtkEntityAppExt, ldtk::{LayerInstance, TilesetDefinition}, prelude::{LdtkEntity, LdtkFields}, EntityIid, EntityInstance};
 
-use crate::{character::Player, level_loading::{CurrentLevel, CurrentLevelChangedEvent}, post_process::PaletteSwapPostProcessSettings, util::run_if_ldtk_project_resource_available};
+use crate::{character::Player, level_loading::{CurrentLevel, CurrentLevelChangedEvent}, post_process::PaletteSwapPostProcessSettings, util::run_if_ldtk_project_resource_available, world_data::{LdtkProjects, WorldDataError, WorldDataErrors}};
 
 #[derive(Default, Component)]
 struct BGM {
@@ -25,6 +25,18 @@ impl Default for FadeStyle {
     }
 }
 
+// How loud the music is, from 0 to 1. Changed from the options menu.
+#[derive(Resource)]
+pub struct MusicVolume {
+    pub volume: f32
+}
+
+impl Default for MusicVolume {
+    fn default() -> Self {
+        Self { volume: 1.0 }
+    }
+}
+
 #[derive(Default, Component)]
 struct Fade {
     fade_style: FadeStyle,
@@ -34,6 +46,7 @@ struct Fade {
 
 fn enact_fade(mut commands: Commands,
               delta_time: Res<Time>,
+              music_volume: Res<MusicVolume>,
               mut fade_query: Query<(Entity, &mut Fade, &AudioSink)>) {
 
     let delta = delta_time.delta();
@@ -50,7 +63,7 @@ fn enact_fade(mut commands: Commands,
             FadeStyle::FadeOut => {
                 (1.0 - (fade.time_used.as_secs_f32() / fade.time.as_secs_f32())).clamp(0.0, 1.0)
             }
-        });
+        } * music_volume.volume);
 
         println!("{}", sink.volume());
 
@@ -68,14 +81,31 @@ fn enact_fade(mut commands: Commands,
     }
 }
 
+// The path of the track that's playing (or fading in), so it can be saved.
+#[derive(Default, Resource)]
+pub struct CurrentBgm {
+    pub path: Option<String>
+}
+
 #[derive(Event)]
-enum BGMControlEvent {
+pub(crate) enum BGMControlEvent {
     FadeTo(Handle<AudioSource>, Duration),
     Change(Handle<AudioSource>),
     Stop
 }
 
+// Keep the music that's already playing at the right volume. (fades take care of themselves)
+fn apply_music_volume(music_volume: Res<MusicVolume>,
+                      bgm_query: Query<&AudioSink, (With<BGM>, Without<Fade>)>) {
+    if music_volume.is_changed() {
+        for sink in &bgm_query {
+            sink.set_volume(music_volume.volume);
+        }
+    }
+}
+
 fn bgm_change(mut commands: Commands,
+              music_volume: Res<MusicVolume>,
               bgm_query: Query<Entity, With<BGM>>,
               mut bgm_control_event_reader: EventReader<BGMControlEvent>) {
 
@@ -94,6 +124,7 @@ fn bgm_change(mut commands: Commands,
                         settings: PlaybackSettings {
                             mode: PlaybackMode::Loop,
                             paused: false,
+                            volume: Volume::new(music_volume.volume),
                             ..Default::default()
                         }
                     },
@@ -133,16 +164,13 @@ fn bgm_change(mut commands: Commands,
 }
 
 fn check_bgm(mut commands: Commands,
+             mut current_bgm: ResMut<CurrentBgm>,
              player_query: Query<(&EntityIid, &CurrentLevel), With<Player>>,
              mut bgm_control_event_writer: EventWriter<BGMControlEvent>,
              mut current_level_event_reader: EventReader<CurrentLevelChangedEvent>,
-             ldtk_project_entities: Query<&Handle<LdtkProject>>,
-             ldtk_project_assets: Res<Assets<LdtkProject>>,
-             asset_server: Res<AssetServer>) {
-
-    // Get the ldtk project so that we can get the level data from it.
-    let ldtk_project = ldtk_project_assets.get(ldtk_project_entities.single())
-        .expect("LdtkProject should be loaded when level is spawned");
+             ldtk_projects: LdtkProjects,
+             asset_server: Res<AssetServer>,
+             mut world_data_errors: ResMut<WorldDataErrors>) {
 
     // Get the player entity
     if let Ok((player_entity_iid, _)) = player_query.get_single() {
@@ -155,14 +183,30 @@ fn check_bgm(mut commands: Commands,
                 if entity_iid == player_entity_iid {
 
                     // The player entered a new level and it loaded.
-                    let level = ldtk_project.get_raw_level_by_iid(level_iid.get()).expect("Level supposedly loaded should exist!");
-                    if let Ok(bgm_path) = level.get_file_path_field("BGM") {
+                    let level = match ldtk_projects.level(level_iid.get()) {
+                        Ok((_, level)) => level,
+                        Err(error) => {
+                            world_data_errors.report(error);
+                            continue;
+                        }
+                    };
+
+                    // Levels without any music just keep playing whatever was on already.
+                    let bgm_path = match level.get_maybe_file_path_field("BGM") {
+                        Ok(bgm_path) => bgm_path,
+                        Err(error) => {
+                            world_data_errors.report(WorldDataError::from_field(&level.identifier, error));
+                            continue;
+                        }
+                    };
+                    if let Some(bgm_path) = bgm_path {
 
                         // Let's load the this bgm track and play it.
                         let bgm_handle = asset_server.load::<AudioSource>(bgm_path);
 
                         // Send a message to change the background music.
                         bgm_control_event_writer.send(BGMControlEvent::FadeTo(bgm_handle, Duration::from_secs(1)));
+                        current_bgm.path = Some(bgm_path.clone());
                     }
                 
                 }
@@ -175,6 +219,8 @@ pub struct AudioPlugin;
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<BGMControlEvent>();
-        app.add_systems(Update, (enact_fade, bgm_change, check_bgm.run_if(run_if_ldtk_project_resource_available)));
+        app.init_resource::<CurrentBgm>();
+        app.init_resource::<MusicVolume>();
+        app.add_systems(Update, (enact_fade, apply_music_volume, bgm_change, check_bgm.run_if(run_if_ldtk_project_resource_available)));
     }
 }
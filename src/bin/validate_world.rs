@@ -0,0 +1,250 @@
+// Check the whole LDtk world for mistakes without having to run the game and walk everywhere.
+// Run with `cargo run --bin validate_world` to check every project in the assets folder,
+// optionally with a different assets folder, or the path to a single .ldtk file to check on its own.
+// Prints everything that's wrong, and exits with an error if anything was, so it can gate content merges.
+
+use std::{collections::{HashMap, HashSet, VecDeque}, path::{Path, PathBuf}, process::ExitCode};
+
+use bevy_ecs_ldtk::{ldtk::{EntityInstance, LdtkJson, Level}, prelude::LdtkFields, EntityIid, LevelIid};
+use bevy_plat::{dialogue_graph::{DialogueAction, DialogueGraph}, save::player_start_level, warp::{warp_errors, warp_level_links, warp_target_levels, WarpProject}, world_data::{entity_condition, level_palette, WorldProject, WORLD_PROJECTS}};
+
+const DEFAULT_ASSETS_PATH: &str = "assets";
+
+// One of the projects being checked.
+struct Project {
+    path: String, // As it's named in WORLD_PROJECTS.
+    depth_offset: i32,
+    base_path: PathBuf, // Where the .ldtk file is, everything in it is relative to here.
+    ldtk_json: LdtkJson
+}
+
+// Everything that's wrong, with which level it's in.
+#[derive(Default)]
+struct Problems {
+    problems: Vec<String>
+}
+
+impl Problems {
+    fn add(&mut self, problem: String) {
+        println!("  {}", problem);
+        self.problems.push(problem);
+    }
+}
+
+// Every entity in a level, on any layer.
+fn level_entities(level: &Level) -> impl Iterator<Item = &EntityInstance> {
+    level.layer_instances.iter().flatten().flat_map(|layer_instance| layer_instance.entity_instances.iter())
+}
+
+// File paths in the project are relative to the .ldtk file.
+fn check_file(problems: &mut Problems, base_path: &Path, level: &Level, what: &str, file_path: &str) -> Option<PathBuf> {
+    let full_path = base_path.join(file_path);
+    if full_path.is_file() {
+        Some(full_path)
+    } else {
+        problems.add(format!("{}: {} {} doesn't exist", level.identifier, what, file_path));
+        None
+    }
+}
+
+fn check_palettes(problems: &mut Problems, ldtk_json: &LdtkJson) {
+    println!("Checking palettes...");
+    for level in &ldtk_json.levels {
+        if let Err(error) = level_palette(level) {
+            problems.add(error.to_string());
+        }
+    }
+}
+
+// Conditions on the game flags need to parse, otherwise the game ignores them.
+// (Warp RequiresFlag conditions are checked along with the rest of the warp)
+fn check_conditions(problems: &mut Problems, ldtk_json: &LdtkJson) {
+    println!("Checking conditions...");
+    for level in &ldtk_json.levels {
+        for entity_instance in level_entities(level) {
+            if entity_instance.identifier == "Actor" {
+                if let Err(error) = entity_condition(entity_instance, "VisibleIf") {
+                    problems.add(format!("{}: {}", level.identifier, error));
+                }
+            }
+        }
+    }
+}
+
+// Music, spritesheets and conversations.
+// Returns the levels that any conversations warp to, from the level the conversation is in.
+fn check_files(problems: &mut Problems, ldtk_json: &LdtkJson, base_path: &Path) -> Vec<(LevelIid, EntityIid)> {
+    println!("Checking files...");
+    let mut dialogue_warps = Vec::new();
+
+    for level in &ldtk_json.levels {
+        if let Ok(Some(bgm_path)) = level.get_maybe_file_path_field("BGM") {
+            check_file(problems, base_path, level, "BGM", bgm_path);
+        }
+
+        for entity_instance in level_entities(level) {
+            match entity_instance.identifier.as_str() {
+                "BGM" => {
+                    match entity_instance.get_file_path_field("MusicPath") {
+                        Ok(music_path) => { check_file(problems, base_path, level, "BGM MusicPath", music_path); },
+                        Err(_) => problems.add(format!("{}: BGM {} has no MusicPath", level.identifier, entity_instance.iid))
+                    }
+                },
+                "Actor" | "Player" => {
+                    match entity_instance.get_file_path_field("Spritesheet") {
+                        Ok(spritesheet_path) => { check_file(problems, base_path, level, "Spritesheet", spritesheet_path); },
+                        Err(_) => problems.add(format!("{}: {} {} has no Spritesheet", level.identifier, entity_instance.identifier, entity_instance.iid))
+                    }
+
+                    // Conversations need to parse, and anywhere they warp to needs to be somewhere we can get the level of.
+                    let Ok(Some(dialogue_path)) = entity_instance.get_maybe_file_path_field("DialogueFile") else {
+                        continue;
+                    };
+                    let Some(full_path) = check_file(problems, base_path, level, "DialogueFile", dialogue_path) else {
+                        continue;
+                    };
+                    let graph = match std::fs::read_to_string(&full_path).map_err(|error| error.to_string())
+                        .and_then(|text| ron::de::from_str::<DialogueGraph>(&text).map_err(|error| error.to_string())) {
+                        Ok(graph) => graph,
+                        Err(error) => {
+                            problems.add(format!("{}: couldn't read DialogueFile {}: {}", level.identifier, dialogue_path, error));
+                            continue;
+                        }
+                    };
+                    for node in graph.nodes.values() {
+                        for action in &node.actions {
+                            if let DialogueAction::Warp(target) = action {
+                                dialogue_warps.push((LevelIid::new(level.iid.clone()), EntityIid::new(target.clone())));
+                            }
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    dialogue_warps
+}
+
+fn check_warps(problems: &mut Problems, warp_projects: &[WarpProject]) {
+    println!("Checking warps...");
+    for error in warp_errors(warp_projects) {
+        problems.add(error.to_string());
+    }
+}
+
+// Every level should be possible to get to, by walking over the edge of a level or by warping.
+// Warps can cross between projects, so this looks at all of them at once.
+fn check_reachable(problems: &mut Problems, projects: &[Project], warp_projects: &[WarpProject], dialogue_warps: Vec<(LevelIid, EntityIid)>) {
+    println!("Checking every level can be reached...");
+    let all_levels = || projects.iter().flat_map(|project| project.ldtk_json.levels.iter());
+    let level_name = |level_iid: &LevelIid| all_levels()
+        .find(|level| level.iid == *level_iid.get())
+        .map_or(level_iid.get().clone(), |level| level.identifier.clone());
+
+    // The game starts in the first project with a Player.
+    let Some(start_level) = projects.iter().find_map(|project| player_start_level(&project.ldtk_json)) else {
+        problems.add(String::from("There's no Player, so there's no start level"));
+        return;
+    };
+
+    // Where you can get to from each level.
+    // Only neighbours on the same world_depth can be walked to, the same as when the game loads them.
+    let mut links: HashMap<LevelIid, HashSet<LevelIid>> = HashMap::new();
+    for project in projects {
+        let levels = &project.ldtk_json.levels;
+        for level in levels {
+            for neighbour in &level.neighbours {
+                let same_depth = levels.iter().any(|neighbour_level| neighbour_level.iid == neighbour.level_iid && neighbour_level.world_depth == level.world_depth);
+                if same_depth {
+                    links.entry(LevelIid::new(level.iid.clone())).or_default().insert(LevelIid::new(neighbour.level_iid.clone()));
+                }
+            }
+        }
+    }
+    for (from_level, to_level) in warp_level_links(warp_projects) {
+        links.entry(from_level).or_default().insert(to_level);
+    }
+    let target_levels = warp_target_levels(warp_projects);
+    for (from_level, target) in dialogue_warps {
+        match target_levels.get(&target) {
+            Some(to_level) => { links.entry(from_level).or_default().insert(to_level.clone()); },
+            None => problems.add(format!("{}: a conversation warps to {}, which isn't a WarpTarget", level_name(&from_level), target.as_str()))
+        }
+    }
+
+    let mut reached = HashSet::from([start_level.clone()]);
+    let mut to_visit = VecDeque::from([start_level]);
+    while let Some(level_iid) = to_visit.pop_front() {
+        for next_level in links.get(&level_iid).into_iter().flatten() {
+            if reached.insert(next_level.clone()) {
+                to_visit.push_back(next_level.clone());
+            }
+        }
+    }
+
+    for level in all_levels() {
+        if !reached.contains(&LevelIid::new(level.iid.clone())) {
+            problems.add(format!("{}: can't be reached from the start level", level.identifier));
+        }
+    }
+}
+
+// Read a project, relative to the assets folder.
+fn read_project(index: usize, assets_path: &Path, path: &str) -> Result<Project, String> {
+    let full_path = assets_path.join(path);
+    let ldtk_json = std::fs::read_to_string(&full_path).map_err(|error| error.to_string())
+        .and_then(|text| serde_json::from_str::<LdtkJson>(&text).map_err(|error| error.to_string()))
+        .map_err(|error| format!("Couldn't read {}: {}", full_path.display(), error))?;
+
+    Ok(Project {
+        path: path.to_string(),
+        depth_offset: WorldProject::new(index, path).depth_offset,
+        base_path: full_path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        ldtk_json
+    })
+}
+
+fn main() -> ExitCode {
+    let path = std::env::args().nth(1).unwrap_or(String::from(DEFAULT_ASSETS_PATH));
+
+    // Either just the one project, or every project the game uses.
+    let projects = if path.ends_with(".ldtk") {
+        let full_path = Path::new(&path);
+        let file_name = full_path.file_name().and_then(|file_name| file_name.to_str()).unwrap_or_default();
+        vec![read_project(0, full_path.parent().unwrap_or(Path::new("")), file_name)]
+    } else {
+        WORLD_PROJECTS.iter().enumerate().map(|(index, project_path)| read_project(index, Path::new(&path), project_path)).collect()
+    };
+    let projects = match projects.into_iter().collect::<Result<Vec<_>, _>>() {
+        Ok(projects) => projects,
+        Err(error) => {
+            println!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let warp_projects = projects.iter()
+        .map(|project| WarpProject { path: &project.path, depth_offset: project.depth_offset, ldtk_json: &project.ldtk_json })
+        .collect::<Vec<_>>();
+
+    println!("Validating {}", projects.iter().map(|project| project.path.as_str()).collect::<Vec<_>>().join(", "));
+
+    let mut problems = Problems::default();
+    check_warps(&mut problems, &warp_projects);
+    let mut dialogue_warps = Vec::new();
+    for project in &projects {
+        check_palettes(&mut problems, &project.ldtk_json);
+        check_conditions(&mut problems, &project.ldtk_json);
+        dialogue_warps.extend(check_files(&mut problems, &project.ldtk_json, &project.base_path));
+    }
+    check_reachable(&mut problems, &projects, &warp_projects, dialogue_warps);
+
+    if problems.problems.is_empty() {
+        println!("All good!");
+        ExitCode::SUCCESS
+    } else {
+        println!("Found {} problem(s).", problems.problems.len());
+        ExitCode::FAILURE
+    }
+}
@@ -68,6 +68,7 @@ impl Default for PixelCameraBundle {
                           Vec3::new(colour_three.red, colour_three.green, colour_three.blue), 
                           Vec3::new(colour_four.red, colour_four.green, colour_four.blue)],
                 darkness: 0,
+                ..default()
             }
         }
     }
@@ -3,12 +3,29 @@ use std::time::Duration;
 use bevy::{ecs::world, prelude::*, scene::ron::de, sprite::{Material2d, MaterialMesh2dBundle}, transform::components};
 use bevy_ecs_tilemap::prelude::*;
 use bevy_ecs_ldtk::{assets::{InternalLevels, LdtkJsonWithMetadata}, prelude::*};
+use serde::{Deserialize, Serialize};
 
-use crate::{camera::PlayerFollowCameraBundle, collision::{self, BlockedTilesCache, Blocking, WorldGridCoords, WorldGridCoordsRequired}, level_loading::CurrentLevel, post_process::PaletteSwapPostProcessSettings};
+use crate::{camera::PlayerFollowCameraBundle, collision::{self, BlockedTilesCache, Blocking, TileOccupancy, WorldGridCoords, WorldGridCoordsRequired}, dialogue::{Dialogue, InDialogue}, input::{Action, ActionInput, InputBindings}, interaction::Interactable, items::{ChestBundle, Inventory, ItemBundle}, level_loading::CurrentLevel, npc::NpcBehaviour, persistence::Persistent, post_process::PaletteSwapPostProcessSettings, terrain::{TerrainCache, TerrainType}, flags::{FlagCondition, GameFlags}, warp::WarpPending, world_data::{self, WorldDataError, WorldDataProblems}};
 
 const MOVEMENT_TICK: f32 = 20.0 / 60.0;
 const ANIMATION_FRAME_TIME: f32 = MOVEMENT_TICK / 2.0;
 
+// How much faster running is than walking.
+const RUN_SPEED_MULTIPLIER: f32 = 2.0;
+
+// The slowest anything is allowed to move.
+const MIN_SPEED: f32 = 0.1;
+
+// The "B" button, hold it to run.
+const RUN_ACTION: Action = Action::B;
+
+// With turn in place, a direction has to be held this long (in seconds) before we start walking in it.
+const TURN_HOLD_TIME: f32 = 0.1;
+
+// Hopping down a ledge covers two tiles, and takes a bit longer than a step.
+const HOP_TICK: f32 = MOVEMENT_TICK * 1.5;
+const HOP_HEIGHT: f32 = 8.0; // Pixels at the top of the hop.
+
 // Makes an entity locked to the tile grid.
 #[derive(Component)]
 pub struct TileLocked {
@@ -21,8 +38,8 @@ fn world_grid_coord_to_world_pixel(world_grid_coords: &WorldGridCoords) -> Vec2
 }
 
 // A direction that a TileMover could be moving in.
-#[derive(PartialEq, Clone, Copy)]
-enum MoveDir {
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MoveDir {
     Up,
     Down,
     Left,
@@ -30,8 +47,8 @@ enum MoveDir {
     NotMoving
 }
 
-#[derive(PartialEq, Clone, Copy)]
-enum FacingDir {
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum FacingDir {
     Up,
     Down,
     Left,
@@ -39,7 +56,7 @@ enum FacingDir {
 }
 
 // Convert a movedir to an IVec
-fn movedir_to_vec(dir: MoveDir) -> IVec2 {
+pub fn movedir_to_vec(dir: MoveDir) -> IVec2 {
     match dir {
         MoveDir::Up => IVec2::new(0, 1),
         MoveDir::Down => IVec2::new(0, -1),
@@ -49,37 +66,115 @@ fn movedir_to_vec(dir: MoveDir) -> IVec2 {
     }
 }
 
+// Convert a facingdir to an IVec
+pub fn facingdir_to_vec(dir: FacingDir) -> IVec2 {
+    match dir {
+        FacingDir::Up => IVec2::new(0, 1),
+        FacingDir::Down => IVec2::new(0, -1),
+        FacingDir::Left => IVec2::new(-1, 0),
+        FacingDir::Right => IVec2::new(1, 0)
+    }
+}
+
+// Which way to move to go the way we're facing.
+pub fn facingdir_to_movedir(dir: FacingDir) -> MoveDir {
+    match dir {
+        FacingDir::Up => MoveDir::Up,
+        FacingDir::Down => MoveDir::Down,
+        FacingDir::Left => MoveDir::Left,
+        FacingDir::Right => MoveDir::Right
+    }
+}
+
+// The direction you'd need to face to look back at something facing this way.
+pub fn opposite_facingdir(dir: FacingDir) -> FacingDir {
+    match dir {
+        FacingDir::Up => FacingDir::Down,
+        FacingDir::Down => FacingDir::Up,
+        FacingDir::Left => FacingDir::Right,
+        FacingDir::Right => FacingDir::Left
+    }
+}
+
 // Makes an entity able to move between tiles.
 #[derive(Component)]
 pub struct TileMover {
-    want_move_dir: MoveDir, // The direction we want to move.
+    pub(crate) want_move_dir: MoveDir, // The direction we want to move.
     moving_dir: MoveDir, // The direction we are currently moving in.
-    facing_dir: FacingDir, // The direction we are facing. (the last moving_dir value that wasn't NotMoving)
+    pub(crate) facing_dir: FacingDir, // The direction we are facing. (the last moving_dir value that wasn't NotMoving)
+    hopping: bool, // Whether the current move is a hop down a ledge, over two tiles.
+    pub speed: f32, // How fast we walk, 1.0 being normal speed.
+    pub running: bool, // Whether we're running rather than walking.
     timer: Timer // Process a movement when this timer is up.
 }
 
+impl TileMover {
+    // Whether we are currently sat still on a tile (not part way through a move).
+    pub fn is_stationary(&self) -> bool {
+        self.timer.finished()
+    }
+
+    pub fn facing_dir(&self) -> FacingDir {
+        self.facing_dir
+    }
+
+    // How fast we're actually going, taking running into account.
+    pub fn current_speed(&self) -> f32 {
+        // Never let it hit zero, or a single step would take forever.
+        let speed = self.speed.max(MIN_SPEED);
+        if self.running { speed * RUN_SPEED_MULTIPLIER } else { speed }
+    }
+
+    // How many tiles the current move covers.
+    fn move_distance(&self) -> i32 {
+        if self.hopping { 2 } else { 1 }
+    }
+
+    // How far up off the ground we are, part way through a hop.
+    pub fn hop_offset(&self) -> f32 {
+        if !self.hopping || self.timer.finished() {
+            return 0.0;
+        }
+
+        // A nice parabola, 0 at either end and HOP_HEIGHT in the middle.
+        let t = self.timer.fraction();
+        HOP_HEIGHT * 4.0 * t * (1.0 - t)
+    }
+}
+
 impl Default for TileMover {
     fn default() -> Self {
         Self {
             want_move_dir: MoveDir::NotMoving,
             moving_dir: MoveDir::NotMoving,
             facing_dir: FacingDir::Down,
+            hopping: false,
+            speed: 1.0,
+            running: false,
             timer: Timer::new(Duration::from_secs_f32(MOVEMENT_TICK), TimerMode::Once)
         }
     }
 }
 
 // Sent whenever an entity moves to another tile.
-#[derive(Event)]
+#[derive(Clone, Debug, Event)]
 pub struct TileMovedEvent {
     pub entity: Entity,
     pub pos: IVec2
 }
 
-fn tile_movement_tick(time: Res<Time>, blocked_tile_cache: Res<BlockedTilesCache>,
+pub(crate) fn tile_movement_tick(time: Res<Time>, blocked_tile_cache: Res<BlockedTilesCache>,
+                      terrain_cache: Res<TerrainCache>,
+                      flags: Res<GameFlags>,
+                      mut tile_occupancy: ResMut<TileOccupancy>,
                       mut tile_moved_event_writer: EventWriter<TileMovedEvent>,
-                      mut query: Query<(Entity, &mut WorldGridCoords, &mut TileMover)>) {
-    for (entity, mut world_grid_coords, mut tile_mover) in query.iter_mut() {
+                      mut query: Query<(Entity, &mut WorldGridCoords, &mut TileMover, Has<Blocking>)>) {
+    for (entity, mut world_grid_coords, mut tile_mover, blocking) in query.iter_mut() {
+        // Make sure we're taking up the tile we're on. (we might have just spawned, or been warped somewhere)
+        if blocking && tile_occupancy.reserved_at(entity) != Some(*world_grid_coords) {
+            tile_occupancy.occupy(entity, *world_grid_coords);
+        }
+
         // Increment timer.
         tile_mover.timer.tick(time.delta());
 
@@ -90,13 +185,18 @@ fn tile_movement_tick(time: Res<Time>, blocked_tile_cache: Res<BlockedTilesCache
             // Trigger a TileMovedEvent, because this is when the tile finished actually moving to the new position.
             if tile_mover.timer.just_finished() {
                 tile_moved_event_writer.send( TileMovedEvent { entity, pos: IVec2::new(world_grid_coords.x, world_grid_coords.y) });
+
+                // We've left the old tile behind now.
+                if blocking {
+                    tile_occupancy.finish_move(entity);
+                }
             }
 
             // If we aren't moving but want to be, process that.
             if tile_mover.want_move_dir != MoveDir::NotMoving {
                 // Find the grid coords that we want to move to.
                 let want_move_dir_vec = movedir_to_vec(tile_mover.want_move_dir);
-                let position_to_move_to = WorldGridCoords {
+                let mut position_to_move_to = WorldGridCoords {
                     x: world_grid_coords.x + want_move_dir_vec.x as i32, 
                     y: world_grid_coords.y + want_move_dir_vec.y as i32,
                     z: world_grid_coords.z
@@ -112,9 +212,39 @@ fn tile_movement_tick(time: Res<Time>, blocked_tile_cache: Res<BlockedTilesCache
                 };
 
                 // Determine whether or not we can move into that space.
+                // Either a wall is in the way, the ground there won't let us on, or something else is there (or on its way there).
                 if (blocked_tile_cache.blocked_tile_locations.contains(&position_to_move_to)) {
                     continue;
                 }
+                let terrain = terrain_cache.terrain_at(&position_to_move_to);
+                if !terrain.can_enter(tile_mover.want_move_dir, &flags) {
+                    continue;
+                }
+
+                // Ledges aren't stood on, we hop right over them and land on the other side.
+                let hopping = matches!(terrain, TerrainType::Ledge(_));
+                if hopping {
+                    position_to_move_to.x += want_move_dir_vec.x;
+                    position_to_move_to.y += want_move_dir_vec.y;
+
+                    // Make sure there's somewhere to land, and that it isn't another ledge.
+                    if blocked_tile_cache.blocked_tile_locations.contains(&position_to_move_to) {
+                        continue;
+                    }
+                    let landing_terrain = terrain_cache.terrain_at(&position_to_move_to);
+                    if matches!(landing_terrain, TerrainType::Ledge(_)) || !landing_terrain.can_enter(tile_mover.want_move_dir, &flags) {
+                        continue;
+                    }
+                }
+
+                if tile_occupancy.is_occupied_by_other(&position_to_move_to, entity) {
+                    continue;
+                }
+
+                // Hold on to both tiles until we've finished moving.
+                if blocking {
+                    tile_occupancy.begin_move(entity, *world_grid_coords, position_to_move_to);
+                }
 
                 // Move the to the position immediately. We'll animate moving to that spot.
                 world_grid_coords.x = position_to_move_to.x;
@@ -122,7 +252,10 @@ fn tile_movement_tick(time: Res<Time>, blocked_tile_cache: Res<BlockedTilesCache
 
                 println!("Moving character to {}, {}", world_grid_coords.x, world_grid_coords.y);
 
-                // Start the timer.
+                // Start the timer. Hops take a little longer, and faster movers take less time.
+                tile_mover.hopping = hopping;
+                let move_time = if hopping { HOP_TICK } else { MOVEMENT_TICK } / tile_mover.current_speed();
+                tile_mover.timer.set_duration(Duration::from_secs_f32(move_time));
                 tile_mover.timer.reset();
 
                 // Store the direction we are moving.
@@ -135,7 +268,8 @@ fn tile_movement_tick(time: Res<Time>, blocked_tile_cache: Res<BlockedTilesCache
 
 fn tile_movement_lerp(mut query: Query<(&mut WorldGridCoords, &mut TileMover, &mut Transform)>) {
     for (mut world_grid_coords, mut tile_mover, mut transform) in query.iter_mut() {
-        let move_dir_vec = movedir_to_vec(tile_mover.moving_dir);
+        // Hops start two tiles back.
+        let move_dir_vec = movedir_to_vec(tile_mover.moving_dir) * tile_mover.move_distance();
         let moving_to_pos = world_grid_coord_to_world_pixel(&world_grid_coords);
         let moving_from_gridcoord = WorldGridCoords { x: world_grid_coords.x - move_dir_vec.x, y: world_grid_coords.y - move_dir_vec.y, z: world_grid_coords.z };
         let moving_from_pos = world_grid_coord_to_world_pixel(&moving_from_gridcoord);
@@ -149,10 +283,54 @@ fn tile_movement_lerp(mut query: Query<(&mut WorldGridCoords, &mut TileMover, &m
 
             // TODO: make this work
             transform.translation = Vec3::new(moving_from_pos.x, moving_from_pos.y, z).lerp(Vec3::new(moving_to_pos.x, moving_to_pos.y, z), timer_ratio);
+
+            // Up in the air if we're hopping.
+            transform.translation.y += tile_mover.hop_offset();
         } else {
             // Not moving anymore. 
             transform.translation = Vec3::new(moving_to_pos.x, moving_to_pos.y, z);
             tile_mover.moving_dir = MoveDir::NotMoving;
+            tile_mover.hopping = false;
+        }
+    }
+}
+
+// The shadow left on the ground while hopping.
+#[derive(Component)]
+struct HopShadow;
+
+// Give hopping things a shadow, and keep it on the ground while they're in the air.
+fn hop_shadow(mut commands: Commands,
+              asset_server: Res<AssetServer>,
+              tile_mover_query: Query<(Entity, &TileMover, Option<&Children>)>,
+              mut shadow_query: Query<&mut Transform, With<HopShadow>>) {
+    for (entity, tile_mover, children) in &tile_mover_query {
+        let shadow = children.and_then(|children| children.iter().find(|child| shadow_query.contains(**child)).copied());
+        let in_the_air = tile_mover.hopping && !tile_mover.is_stationary();
+
+        match (in_the_air, shadow) {
+            (true, None) => {
+                // Just behind whoever is hopping.
+                commands.entity(entity).with_children(|parent| {
+                    parent.spawn((
+                        SpriteBundle {
+                            texture: asset_server.load("shadow.png"),
+                            transform: Transform::from_xyz(0.0, 0.0, -0.5),
+                            ..default()
+                        },
+                        HopShadow
+                    ));
+                });
+            },
+            (true, Some(shadow)) => {
+                if let Ok(mut transform) = shadow_query.get_mut(shadow) {
+                    transform.translation.y = -tile_mover.hop_offset();
+                }
+            },
+            (false, Some(shadow)) => {
+                commands.entity(shadow).despawn_recursive();
+            },
+            (false, None) => {}
         }
     }
 }
@@ -228,12 +406,14 @@ fn animate_sprite(
     mut query: Query<(
         &AnimationIndices,
         &mut AnimationTimer,
-        &mut TextureAtlas
+        &mut TextureAtlas,
+        Option<&TileMover>
     )>,
 ) {
-    for (anim_indices, mut timer, mut sprite) in &mut query {
-        // Update our timer.
-        timer.time_animated += time.delta();
+    for (anim_indices, mut timer, mut sprite, tile_mover) in &mut query {
+        // Update our timer. Things that move faster animate faster too.
+        let speed = tile_mover.map_or(1.0, |tile_mover| tile_mover.current_speed());
+        timer.time_animated += time.delta().mul_f32(speed);
 
         // Calculate what frame we should be at.
         let range = anim_indices.last - anim_indices.first;
@@ -267,7 +447,14 @@ struct ActorBundle {
     pub grid_coords: GridCoords,
     world_grid_coords_required: WorldGridCoordsRequired,
 
-    blocking: Blocking
+    blocking: Blocking,
+    interactable: Interactable,
+    dialogue: Dialogue,
+    npc_behaviour: NpcBehaviour,
+    visible_if: VisibleIf,
+    persistent: Persistent,
+
+    world_data_problems: WorldDataProblems
 }
 
 impl LdtkEntity for ActorBundle {
@@ -277,14 +464,44 @@ impl LdtkEntity for ActorBundle {
                      tileset_definition: Option<&TilesetDefinition>,
                      asset_server: &AssetServer,
                      texture_atlases: &mut Assets<TextureAtlasLayout>) -> Self {
-        
-        println!("SPAWNING ACTOR");
 
-        // Get the spritesheet filename.
-        let spritesheet_path = entity_instance.get_file_path_field("Spritesheet").expect("Actor or Player should have a Spritesheet!");
+        let mut world_data_problems = WorldDataProblems::default();
 
         // Load/Get the spritesheet from our assets.
-        let spritesheet_texture = asset_server.load(spritesheet_path);
+        // Without one the actor is still there, you just can't see it.
+        let spritesheet_texture = match entity_instance.get_file_path_field("Spritesheet") {
+            Ok(spritesheet_path) => asset_server.load(spritesheet_path),
+            Err(error) => {
+                let owner = format!("{} {}", entity_instance.identifier, entity_instance.iid);
+                world_data_problems.0.push(WorldDataError::from_field(&owner, error));
+                Handle::default()
+            }
+        };
+
+        // What the actor says when talked to, each entry is shown starting on a new page.
+        let dialogue = Dialogue {
+            pages: entity_instance.get_maybe_strings_field("Dialogue")
+                .map(|lines| lines.iter().flatten().cloned().collect())
+                .unwrap_or_default()
+        };
+
+        // How fast the actor walks about.
+        let tile_mover = TileMover {
+            speed: entity_instance.get_float_field("Speed").copied().unwrap_or(1.0),
+            ..Default::default()
+        };
+
+        // Only show up once the story has got far enough, or until it's moved on. (None means always)
+        // A condition that doesn't make sense is reported, and the actor is left always visible.
+        let visible_if = VisibleIf {
+            condition: match world_data::entity_condition(entity_instance, "VisibleIf") {
+                Ok(condition) => condition,
+                Err(error) => {
+                    world_data_problems.0.push(error);
+                    None
+                }
+            }
+        };
 
         // Layout for the texture atlas
         let spritesheet_layout = TextureAtlasLayout::from_grid(UVec2::splat(16), 16, 1, None, None);
@@ -305,11 +522,48 @@ impl LdtkEntity for ActorBundle {
                 }
             },
             grid_coords: GridCoords::from_entity_info(entity_instance, layer_instance),
+            tile_mover,
+            dialogue,
+            npc_behaviour: NpcBehaviour::from_entity_info(entity_instance, layer_instance),
+            visible_if,
+            world_data_problems,
             ..Default::default()
         }
     }
 }
 
+// Actors that should only be around while a condition on the game flags holds.
+#[derive(Clone, Debug, Default, Component)]
+pub struct VisibleIf {
+    pub condition: Option<FlagCondition>
+}
+
+// Marks an actor that's been hidden because its VisibleIf condition doesn't hold.
+// Hidden actors can't be bumped into or talked to, and don't wander about.
+#[derive(Default, Component)]
+pub struct HiddenByFlags;
+
+// Show and hide actors as the game flags change.
+fn apply_visible_if(mut commands: Commands,
+                    flags: Res<GameFlags>,
+                    mut query: Query<(Entity, &VisibleIf, &mut Visibility, Has<HiddenByFlags>)>) {
+    for (entity, visible_if, mut visibility, hidden) in &mut query {
+        let visible = visible_if.condition.as_ref().is_none_or(|condition| condition.evaluate(&flags));
+
+        if !visible && !hidden {
+            *visibility = Visibility::Hidden;
+            commands.entity(entity)
+                .insert(HiddenByFlags)
+                .remove::<(Blocking, Interactable)>();
+        } else if visible && hidden {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity)
+                .remove::<HiddenByFlags>()
+                .insert((Blocking, Interactable));
+        }
+    }
+}
+
 #[derive(Default, Component)]
 pub struct Player;
 
@@ -319,6 +573,7 @@ pub struct PlayerBundle {
 
     player: Player,
     current_level: CurrentLevel,
+    inventory: Inventory,
 
     worldly: Worldly
 }
@@ -346,34 +601,93 @@ impl Plugin for CharacterPlugin {
         // Register the player and actor entities.
         app.register_ldtk_entity::<ActorBundle>("Actor");
         app.register_ldtk_entity::<PlayerBundle>("Player");
+        app.register_ldtk_entity::<ItemBundle>("Item");
+        app.register_ldtk_entity::<ChestBundle>("Chest");
 
         // Actor creation
         //app.add_systems(FixedUpdate, actor_added);
 
         // Manage character movement.        
-        app.add_systems(FixedUpdate, (animate_sprite, move_player));
+        // The player decides where to go next after movement has been processed, so we know whether
+        // they're stood still and can act on buffered input.
+        app.add_systems(FixedUpdate, (animate_sprite, move_player.after(tile_movement_tick)));
         app.add_systems(FixedUpdate, (tile_movement_tick,
                                                         tile_movement_lerp,
                                                         walk_anim_control));
+        app.add_systems(FixedUpdate, hop_shadow.after(tile_movement_lerp));
+
+        // Check straight away in Update, so hidden actors don't pop up for a frame before FixedUpdate gets to them.
+        app.add_systems(Update, apply_visible_if);
 
         app.add_event::<TileMovedEvent>();
     }
 }
 
-fn move_player(keys: Res<ButtonInput<KeyCode>>, mut query: Query<(&Player, &mut TileMover)>) {
-    for (player, mut tile_mover) in query.iter_mut() {
-        tile_mover.want_move_dir = if keys.pressed(KeyCode::ArrowUp) {
-            MoveDir::Up
-        } else if keys.pressed(KeyCode::ArrowDown) {
-            MoveDir::Down
+fn action_to_movedir(action: Action) -> MoveDir {
+    match action {
+        Action::Up => MoveDir::Up,
+        Action::Down => MoveDir::Down,
+        Action::Left => MoveDir::Left,
+        Action::Right => MoveDir::Right,
+        _ => MoveDir::NotMoving
+    }
+}
+
+pub fn movedir_to_facingdir(dir: MoveDir) -> Option<FacingDir> {
+    match dir {
+        MoveDir::Up => Some(FacingDir::Up),
+        MoveDir::Down => Some(FacingDir::Down),
+        MoveDir::Left => Some(FacingDir::Left),
+        MoveDir::Right => Some(FacingDir::Right),
+        MoveDir::NotMoving => None
+    }
+}
+
+pub(crate) fn move_player(mut action_input: ResMut<ActionInput>,
+               input_bindings: Res<InputBindings>,
+               mut turning: Local<bool>, // Whether we've just turned on the spot and are waiting to see if the direction is held.
+               mut query: Query<(&Player, &mut TileMover, Has<WarpPending>, Has<InDialogue>)>) {
+    for (player, mut tile_mover, warp_pending, in_dialogue) in query.iter_mut() {
+        tile_mover.running = action_input.pressed(RUN_ACTION);
+
+        // Locked in place.
+        if warp_pending || in_dialogue {
+            tile_mover.want_move_dir = MoveDir::NotMoving;
+            continue;
         }
-        else if keys.pressed(KeyCode::ArrowLeft) {
-            MoveDir::Left
+
+        // If more than one direction is held, the one pressed last wins.
+        let held_direction = action_input.direction();
+
+        // Mid step, only keep going if a direction is actually held.
+        // A tap that's already been let go of stays in the buffer until we're stood still.
+        if !tile_mover.is_stationary() {
+            tile_mover.want_move_dir = held_direction.map_or(MoveDir::NotMoving, action_to_movedir);
+            action_input.hold_buffer(tile_mover.timer.elapsed_secs());
+            continue;
         }
-        else if keys.pressed(KeyCode::ArrowRight) {
-            MoveDir::Right
-        } else {
+
+        let Some(direction) = held_direction.or_else(|| action_input.buffered_direction()) else {
+            *turning = false;
+            tile_mover.want_move_dir = MoveDir::NotMoving;
+            continue;
+        };
+        action_input.consume(direction);
+
+        let move_dir = action_to_movedir(direction);
+        let facing_dir = movedir_to_facingdir(move_dir).unwrap_or(tile_mover.facing_dir);
+
+        tile_mover.want_move_dir = if input_bindings.turn_in_place && facing_dir != tile_mover.facing_dir {
+            // Just turn to face the new direction for now.
+            tile_mover.facing_dir = facing_dir;
+            *turning = true;
             MoveDir::NotMoving
-        }
+        } else if *turning && held_direction.is_some() && action_input.held_for(direction).unwrap_or(0.0) < TURN_HOLD_TIME {
+            // Only turned a moment ago, don't start walking unless it's held a little longer.
+            MoveDir::NotMoving
+        } else {
+            *turning = false;
+            move_dir
+        };
     }
-}
\ No newline at end of file
+}
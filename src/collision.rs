@@ -1,26 +1,26 @@
-use std::{collections::HashSet, thread::current};
+use std::{collections::{HashMap, HashSet}, thread::current};
 
 use bevy::prelude::*;
 use bevy_ecs_ldtk::{prelude::*, utils::{self, ldtk_grid_coords_to_grid_coords, ldtk_pixel_coords_to_grid_coords, ldtk_pixel_coords_to_translation, translation_to_grid_coords}};
 use bevy_ecs_tilemap::prelude::*;
-use bevy::{app::{App, Plugin, Update}, asset::{Assets, Handle}, ecs::{entity, world}, math::IVec2, prelude::{Added, Bundle, Commands, Component, Entity, EventReader, Query, Res, ResMut, Resource, With, World}};
-use bevy_ecs_ldtk::{app::LdtkIntCellAppExt, assets::{LdtkProject, LevelMetadataAccessor}, EntityInstance, GridCoords, IntGridCell, LdtkIntCell, LevelEvent};
+use bevy::{app::{App, Plugin, Update}, ecs::{entity, world}, math::IVec2, prelude::{Added, Bundle, Commands, Component, Entity, EventReader, Query, ResMut, Resource, With, World}};
+use bevy_ecs_ldtk::{app::LdtkIntCellAppExt, EntityInstance, GridCoords, IntGridCell, LdtkIntCell, LevelEvent};
 
 use bevy_ecs_ldtk::app::LdtkEntity;
 use bevy_inspector_egui::egui::Grid;
 use ldtk::loaded_level::LoadedLevel;
 
-use crate::util;
+use crate::{character::TileMover, util, world_data::{LdtkProjects, WorldDataErrors}};
+use serde::{Deserialize, Serialize};
 
 pub const TILE_GRID_SIZE: IVec2 = IVec2::new(16, 16);
-const BLOCKED_TILE_GRID_CELL: i32 = 1;
 
 // This will be swapped out for a valid worldgridcoords
 #[derive(Debug, Default, Clone, Component)]
 pub struct WorldGridCoordsRequired;
 
 // A grid coordinate in world coordinates
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Component, Serialize, Deserialize)]
 pub struct WorldGridCoords {
     pub x: i32,
     pub y: i32, 
@@ -28,17 +28,13 @@ pub struct WorldGridCoords {
 }
 
 // A system that should set the world grid coords component to have correct world grid coordinates.
-fn world_grid_coords_required(mut commands: Commands,
+pub(crate) fn world_grid_coords_required(mut commands: Commands,
                               mut world_grid_coords_query: Query<(Entity, &GridCoords, &Parent), Added<WorldGridCoordsRequired>>,
                               parent_query: Query<&Parent, Without<WorldGridCoordsRequired>>,
                               level_query: Query<&LevelIid>,
-                              ldtk_project_entities: Query<&Handle<LdtkProject>>,
-                              ldtk_project_assets: Res<Assets<LdtkProject>>) {
+                              ldtk_projects: LdtkProjects,
+                              mut world_data_errors: ResMut<WorldDataErrors>) {
 
-    // Get the ldtk project so that we can get the level data from it.
-    let ldtk_project = ldtk_project_assets.get(ldtk_project_entities.single())
-                                    .expect("LdtkProject should be loaded when level is spawned");
-    
     for (entity, grid_coords, parent) in world_grid_coords_query.iter_mut() {
         // The Parent's Parent is the level entity, so we can use that
         // to get the offset for the grid coords \o/
@@ -46,8 +42,14 @@ fn world_grid_coords_required(mut commands: Commands,
         if let Ok(level_parent) = parent_query.get(layer_entity) {
             // Find the level iid
             if let Ok(level_iid) = level_query.get(level_parent.get()) {
-                // Now finally get the level.
-                let level = ldtk_project.get_raw_level_by_iid(level_iid.get()).expect("world grid coord tile should have a grandparent level!");
+                // Now finally get the level, from whichever project it's in.
+                let (loaded_project, level) = match ldtk_projects.level(level_iid.get()) {
+                    Ok(found) => found,
+                    Err(error) => {
+                        world_data_errors.report(error);
+                        continue;
+                    }
+                };
                 
                 //println!("level world pos: {}, {}, {}", level.world_x, level.world_y, level.world_depth);
 
@@ -64,7 +66,7 @@ fn world_grid_coords_required(mut commands: Commands,
                 let world_grid_coords = WorldGridCoords {
                     x: level_origin_adjusted.x + grid_coords.x,
                     y: level_origin_adjusted.y + grid_coords.y,
-                    z: level.world_depth
+                    z: loaded_project.level_depth(level)
                 };
                 commands.entity(entity).insert(world_grid_coords);
 
@@ -79,47 +81,246 @@ fn world_grid_coords_required(mut commands: Commands,
 #[derive(Clone, Debug, Default, Component)]
 pub struct Blocking; 
 
-#[derive(Clone, Debug, Default, Bundle, LdtkIntCell)]
-pub struct BlockedTileBundle {
-    blocked_tile: Blocking,
-    world_grid_coords_required: WorldGridCoordsRequired
+// Where each never-moving tile entity is and which level it came from, so it can be forgotten on its own
+// or along with the rest of its level. Both the BlockedTilesCache and the TerrainCache keep one of these.
+#[derive(Default)]
+pub struct LevelTileIndex {
+    tiles: HashMap<Entity, (WorldGridCoords, Option<LevelIid>)>,
+    level_tiles: HashMap<LevelIid, HashSet<Entity>>
+}
+
+impl LevelTileIndex {
+    // Hands back where the entity used to be, if it was already in here.
+    pub fn insert(&mut self, entity: Entity, world_grid_coords: WorldGridCoords, level_iid: Option<LevelIid>) -> Option<WorldGridCoords> {
+        let old_world_grid_coords = self.remove(entity);
+
+        if let Some(level_iid) = &level_iid {
+            self.level_tiles.entry(level_iid.clone()).or_default().insert(entity);
+        }
+        self.tiles.insert(entity, (world_grid_coords, level_iid));
+
+        old_world_grid_coords
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<WorldGridCoords> {
+        let (world_grid_coords, level_iid) = self.tiles.remove(&entity)?;
+
+        // Don't leave it behind in its level's set either.
+        if let Some(level_iid) = level_iid {
+            if let Some(entities) = self.level_tiles.get_mut(&level_iid) {
+                entities.remove(&entity);
+                if entities.is_empty() {
+                    self.level_tiles.remove(&level_iid);
+                }
+            }
+        }
+
+        Some(world_grid_coords)
+    }
+
+    // Forget about everything that was in a level, handing back what was forgotten and where it was.
+    pub fn evict_level(&mut self, level_iid: &LevelIid) -> Vec<(Entity, WorldGridCoords)> {
+        let entities = self.level_tiles.remove(level_iid).unwrap_or_default();
+        entities.into_iter()
+            .filter_map(|entity| self.tiles.remove(&entity).map(|(world_grid_coords, _)| (entity, world_grid_coords)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
 }
 
 // Maintain a cache of all the tile locations that are blocked
 // This way we can easily tell if a location can't be occupied by an tile entity.
+// Only things that never move (signs, chests, etc) go in here, things that move are tracked by TileOccupancy.
+// Walls aren't in here either, they're terrain. (see the TerrainCache)
+// The cache is kept up to date as blocking things are spawned and despawned, rather than being rebuilt every tick.
 #[derive(Default, Resource)]
 pub struct BlockedTilesCache {
-    pub blocked_tile_locations: HashSet<WorldGridCoords>
+    pub blocked_tile_locations: HashSet<WorldGridCoords>,
+
+    // Where each blocking entity is (and which level it's from), so we know what to remove when it goes away.
+    blockers: LevelTileIndex,
+
+    // How many blocking entities are on each tile. A tile is only unblocked when the last one goes.
+    blocker_counts: HashMap<WorldGridCoords, usize>
+}
+
+impl BlockedTilesCache {
+    pub fn insert(&mut self, entity: Entity, world_grid_coords: WorldGridCoords, level_iid: Option<LevelIid>) {
+        // Already known about, it must have been moved so forget the old spot.
+        if let Some(old_world_grid_coords) = self.blockers.insert(entity, world_grid_coords, level_iid) {
+            self.unblock(old_world_grid_coords);
+        }
+
+        *self.blocker_counts.entry(world_grid_coords).or_insert(0) += 1;
+        self.blocked_tile_locations.insert(world_grid_coords);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(world_grid_coords) = self.blockers.remove(entity) {
+            self.unblock(world_grid_coords);
+        }
+    }
+
+    // Forget about everything that was in a level.
+    pub fn evict_level(&mut self, level_iid: &LevelIid) {
+        for (_, world_grid_coords) in self.blockers.evict_level(level_iid) {
+            self.unblock(world_grid_coords);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blockers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blockers.is_empty()
+    }
+
+    // One less blocking thing on this tile.
+    fn unblock(&mut self, world_grid_coords: WorldGridCoords) {
+        if let Some(count) = self.blocker_counts.get_mut(&world_grid_coords) {
+            *count -= 1;
+            if *count == 0 {
+                self.blocker_counts.remove(&world_grid_coords);
+                self.blocked_tile_locations.remove(&world_grid_coords);
+            }
+        }
+    }
+}
+
+// Find the level a tile or entity belongs to. (the parent is the layer, the layer's parent is the level)
+pub(crate) fn level_of(entity_parent: Option<&Parent>,
+            parent_query: &Query<&Parent>,
+            level_query: &Query<&LevelIid>) -> Option<LevelIid> {
+    let layer_entity = entity_parent?.get();
+    let level_entity = parent_query.get(layer_entity).ok()?.get();
+    level_query.get(level_entity).ok().cloned()
 }
 
-// Whenever a level is loaded, then rebuild our cache.
-fn build_blocked_tile_cache(mut blocked_tiles_cache: ResMut<BlockedTilesCache>,
-                            blocked_tiles: Query<&WorldGridCoords, With<Blocking>>) {
-    
-    // Collect all of the blocked tiles that currently exist.
-    let mut blocked_tile_locations = HashSet::new();
-    for world_grid_coords in blocked_tiles.iter() {
-        blocked_tile_locations.insert(*world_grid_coords);
+// Add blocking things to the cache as soon as they know where they are in the world.
+pub fn add_blocked_tiles(mut blocked_tiles_cache: ResMut<BlockedTilesCache>,
+                         blocked_tiles: Query<(Entity, &WorldGridCoords, Option<&Parent>), (With<Blocking>, Without<TileMover>, Added<WorldGridCoords>)>,
+                         parent_query: Query<&Parent>,
+                         level_query: Query<&LevelIid>) {
+    for (entity, world_grid_coords, parent) in &blocked_tiles {
+        let level_iid = level_of(parent, &parent_query, &level_query);
+        blocked_tiles_cache.insert(entity, *world_grid_coords, level_iid);
     }
+}
 
-    // Build up a new cache.
-    let new_blocked_tiles_cache = BlockedTilesCache {
-        blocked_tile_locations: blocked_tile_locations
-    };
+// Take things out of the cache when they stop blocking or get despawned.
+pub fn remove_blocked_tiles(mut blocked_tiles_cache: ResMut<BlockedTilesCache>,
+                            mut removed_blocking: RemovedComponents<Blocking>) {
+    for entity in removed_blocking.read() {
+        blocked_tiles_cache.remove(entity);
+    }
+}
+
+// When a level goes away, everything in it goes in one go.
+pub fn evict_despawned_level_blocked_tiles(mut blocked_tiles_cache: ResMut<BlockedTilesCache>,
+                                           mut level_event_reader: EventReader<LevelEvent>) {
+    for level_event in level_event_reader.read() {
+        if let LevelEvent::Despawned(level_iid) = level_event {
+            blocked_tiles_cache.evict_level(level_iid);
+        }
+    }
+}
+
+// The tiles a blocking TileMover is taking up.
+#[derive(Clone, Copy, Debug)]
+struct TileReservation {
+    at: WorldGridCoords, // Where the entity is (or is moving to).
+    leaving: Option<WorldGridCoords> // Where it's moving from, it's still in the way until the move finishes.
+}
+
+// Live map of which tiles are taken up by blocking entities that can move.
+// A move reserves the destination tile straight away, and the tile being left is only freed once the move is done,
+// so nothing can walk into either while the move is animating.
+#[derive(Default, Resource)]
+pub struct TileOccupancy {
+    occupied_tiles: HashMap<WorldGridCoords, Entity>,
+    reservations: HashMap<Entity, TileReservation>
+}
+
+impl TileOccupancy {
+    // Who is taking up this tile, if anyone.
+    pub fn occupant(&self, world_grid_coords: &WorldGridCoords) -> Option<Entity> {
+        self.occupied_tiles.get(world_grid_coords).copied()
+    }
 
-    // Update the resource.
-    *blocked_tiles_cache = new_blocked_tiles_cache;
+    // Whether something other than the given entity is in the way at this tile.
+    pub fn is_occupied_by_other(&self, world_grid_coords: &WorldGridCoords, entity: Entity) -> bool {
+        self.occupant(world_grid_coords).is_some_and(|occupant| occupant != entity)
+    }
+
+    // The tile the entity is at (or moving to), if it has a reservation.
+    pub fn reserved_at(&self, entity: Entity) -> Option<WorldGridCoords> {
+        self.reservations.get(&entity).map(|reservation| reservation.at)
+    }
+
+    // The entity is standing still on this tile, forget about anywhere else it was.
+    pub fn occupy(&mut self, entity: Entity, world_grid_coords: WorldGridCoords) {
+        self.release(entity);
+        self.occupied_tiles.insert(world_grid_coords, entity);
+        self.reservations.insert(entity, TileReservation { at: world_grid_coords, leaving: None });
+    }
+
+    // The entity has started moving between two tiles, keep hold of both until it gets there.
+    pub fn begin_move(&mut self, entity: Entity, from: WorldGridCoords, to: WorldGridCoords) {
+        self.release(entity);
+        self.occupied_tiles.insert(from, entity);
+        self.occupied_tiles.insert(to, entity);
+        self.reservations.insert(entity, TileReservation { at: to, leaving: Some(from) });
+    }
+
+    // The entity has arrived, so the tile it left is free again.
+    pub fn finish_move(&mut self, entity: Entity) {
+        if let Some(reservation) = self.reservations.get_mut(&entity) {
+            if let Some(leaving) = reservation.leaving.take() {
+                if self.occupied_tiles.get(&leaving) == Some(&entity) {
+                    self.occupied_tiles.remove(&leaving);
+                }
+            }
+        }
+    }
+
+    // Free up every tile the entity was taking up.
+    pub fn release(&mut self, entity: Entity) {
+        if let Some(reservation) = self.reservations.remove(&entity) {
+            for world_grid_coords in std::iter::once(reservation.at).chain(reservation.leaving) {
+                if self.occupied_tiles.get(&world_grid_coords) == Some(&entity) {
+                    self.occupied_tiles.remove(&world_grid_coords);
+                }
+            }
+        }
+    }
+}
+
+// Entities that stop blocking (or get despawned along with their level) shouldn't leave their tiles taken.
+fn release_tile_occupancy(mut tile_occupancy: ResMut<TileOccupancy>,
+                          mut removed_blocking: RemovedComponents<Blocking>) {
+    for entity in removed_blocking.read() {
+        tile_occupancy.release(entity);
+    }
 }
 
 pub struct CollisionPlugin;
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
-        app.register_ldtk_int_cell::<BlockedTileBundle>(BLOCKED_TILE_GRID_CELL);
-            
         // The resource for the cache.
         app.init_resource::<BlockedTilesCache>();
+        app.init_resource::<TileOccupancy>();
 
         // These should only run if the ldtk project is available.
-        app.add_systems(FixedUpdate, (world_grid_coords_required, build_blocked_tile_cache).run_if(util::run_if_ldtk_project_resource_available));
+        app.add_systems(FixedUpdate, (world_grid_coords_required, add_blocked_tiles).chain().run_if(util::run_if_ldtk_project_resource_available));
+        app.add_systems(FixedUpdate, (evict_despawned_level_blocked_tiles, remove_blocked_tiles).chain());
+        app.add_systems(FixedUpdate, release_tile_occupancy);
     }
 }
\ No newline at end of file
@@ -0,0 +1,380 @@
+// A Game Boy style text box along the bottom of the screen.
+// Text is revealed a letter at a time, split up into pages that fit into the box,
+// and the player presses the action button to move on to the next page.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{character::Player, dialogue_graph::Conversation, font::{PixelText, PixelTextBundle}, input::{Action, ActionInput}, interaction::InteractEvent, post_process::palette_band_colour};
+
+// Same button as interacting.
+const ADVANCE_ACTION: Action = Action::A;
+
+// For moving between choices.
+const CHOICE_UP_ACTION: Action = Action::Up;
+const CHOICE_DOWN_ACTION: Action = Action::Down;
+
+// The box sits along the bottom of the 160x144 view.
+const BOX_SIZE: Vec2 = Vec2::new(160.0, 48.0);
+const BOX_BORDER: f32 = 2.0;
+const BOX_PADDING: Vec2 = Vec2::new(8.0, 8.0);
+const VIEW_HEIGHT: f32 = 144.0;
+
+// Draw over the top of everything in the world.
+const BOX_Z: f32 = 100.0;
+
+// How much text fits into the box, with 6 pixel wide letters.
+const LINE_CHARS: usize = 24;
+const PAGE_LINES: usize = 2;
+
+// The choices box sits above the right hand side of the dialogue box.
+const CHOICE_CHAR_WIDTH: f32 = 6.0;
+const CHOICE_LINE_HEIGHT: f32 = 10.0;
+
+// How fast the text types itself out.
+const CHAR_REVEAL_TIME: Duration = Duration::from_millis(33);
+
+// Some text that can be shown by talking to whoever has it.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Dialogue {
+    pub pages: Vec<String>
+}
+
+// Lock the player in place while they are reading.
+#[derive(Clone, Debug, Default, Component)]
+pub struct InDialogue;
+
+// Ask for a text box to be opened.
+// If there are any choices, they're offered once the last page has been shown.
+#[derive(Clone, Debug, Default, Event)]
+pub struct OpenDialogueEvent {
+    pub text: Vec<String>,
+    pub choices: Vec<String>
+}
+
+// Sent when the player closes a text box, along with which choice they made (if there were any).
+#[derive(Event)]
+pub struct DialogueClosedEvent {
+    pub choice: Option<usize>
+}
+
+// The text box itself, and how far through it we are.
+#[derive(Component)]
+pub(crate) struct DialogueBox {
+    pages: Vec<String>,
+    page: usize,
+    chars_revealed: usize,
+    reveal_timer: Timer,
+
+    choices: Vec<String>,
+    selected_choice: usize
+}
+
+impl DialogueBox {
+    fn current_page(&self) -> &str {
+        &self.pages[self.page]
+    }
+
+    fn page_finished(&self) -> bool {
+        self.chars_revealed >= self.current_page().chars().count()
+    }
+
+    fn last_page(&self) -> bool {
+        self.page + 1 >= self.pages.len()
+    }
+
+    // Choices only show up once everything else has been read.
+    fn showing_choices(&self) -> bool {
+        !self.choices.is_empty() && self.last_page() && self.page_finished()
+    }
+}
+
+// The text part of the dialogue box.
+#[derive(Component)]
+struct DialogueText;
+
+// The box with the choices in it.
+#[derive(Component)]
+struct DialogueChoices;
+
+// The text part of the choices box.
+#[derive(Component)]
+struct DialogueChoicesText;
+
+// Word wrap some text and split it up into pages that fit into the dialogue box.
+// Each bit of text always starts a new page.
+pub fn paginate(text: &[String]) -> Vec<String> {
+    let mut pages = Vec::new();
+
+    for paragraph in text {
+        let mut lines: Vec<String> = Vec::new();
+
+        // Explicit newlines are kept, then each line is wrapped at word boundaries.
+        for paragraph_line in paragraph.lines() {
+            let mut line = String::new();
+            for word in paragraph_line.split_whitespace() {
+                let needed = if line.is_empty() { word.chars().count() } else { line.chars().count() + 1 + word.chars().count() };
+                if needed > LINE_CHARS && !line.is_empty() {
+                    lines.push(line);
+                    line = String::new();
+                }
+
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+            }
+            lines.push(line);
+        }
+
+        // Group the lines into pages.
+        for page_lines in lines.chunks(PAGE_LINES) {
+            pages.push(page_lines.join("\n"));
+        }
+    }
+
+    pages
+}
+
+// Talking to anything with some dialogue opens it up.
+// (Anything with a full conversation uses that instead.)
+fn talk_to_dialogue(mut interact_event_reader: EventReader<InteractEvent>,
+                    mut open_dialogue_event_writer: EventWriter<OpenDialogueEvent>,
+                    dialogue_query: Query<&Dialogue, Without<Conversation>>) {
+    for event in interact_event_reader.read() {
+        if let Ok(dialogue) = dialogue_query.get(event.target) {
+            if !dialogue.pages.is_empty() {
+                open_dialogue_event_writer.send(OpenDialogueEvent {
+                    text: dialogue.pages.clone(),
+                    ..default()
+                });
+            }
+        }
+    }
+}
+
+pub(crate) fn open_dialogue(mut commands: Commands,
+                 mut open_dialogue_event_reader: EventReader<OpenDialogueEvent>,
+                 camera_query: Query<Entity, With<Camera2d>>,
+                 player_query: Query<Entity, With<Player>>,
+                 dialogue_box_query: Query<&DialogueBox>) {
+    for event in open_dialogue_event_reader.read() {
+
+        // Only one box at a time.
+        if !dialogue_box_query.is_empty() {
+            continue;
+        }
+
+        // Choices on their own still need a (blank) page to sit on top of.
+        let mut pages = paginate(&event.text);
+        if pages.is_empty() {
+            if event.choices.is_empty() {
+                continue;
+            }
+            pages.push(String::new());
+        }
+
+        // Size the choices box to fit the longest choice (plus room for the cursor).
+        let longest_choice = event.choices.iter().map(|choice| choice.chars().count()).max().unwrap_or(0);
+        let choices_size = Vec2::new((longest_choice + 2) as f32 * CHOICE_CHAR_WIDTH, event.choices.len() as f32 * CHOICE_LINE_HEIGHT) + BOX_PADDING;
+
+        // The box hangs off the camera so that it stays put on screen.
+        let Ok(camera_entity) = camera_query.get_single() else {
+            continue;
+        };
+
+        commands.entity(camera_entity).with_children(|parent| {
+            // The border.
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: palette_band_colour(3),
+                        custom_size: Some(BOX_SIZE),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, (BOX_SIZE.y - VIEW_HEIGHT) / 2.0, BOX_Z),
+                    ..default()
+                },
+                DialogueBox {
+                    pages,
+                    page: 0,
+                    chars_revealed: 0,
+                    reveal_timer: Timer::new(CHAR_REVEAL_TIME, TimerMode::Repeating),
+                    choices: event.choices.clone(),
+                    selected_choice: 0
+                }
+            )).with_children(|parent| {
+                // The inside of the box.
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: palette_band_colour(0),
+                        custom_size: Some(BOX_SIZE - Vec2::splat(BOX_BORDER * 2.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                    ..default()
+                });
+
+                // The text, starting from the top left corner.
+                parent.spawn((
+                    PixelTextBundle {
+                        text: PixelText::new(""),
+                        spatial: SpatialBundle::from_transform(Transform::from_xyz(BOX_PADDING.x - BOX_SIZE.x / 2.0, BOX_SIZE.y / 2.0 - BOX_PADDING.y, 0.2))
+                    },
+                    DialogueText
+                ));
+
+                // The choices, hidden until they're needed.
+                if !event.choices.is_empty() {
+                    parent.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: palette_band_colour(3),
+                                custom_size: Some(choices_size),
+                                ..default()
+                            },
+                            transform: Transform::from_xyz((BOX_SIZE.x - choices_size.x) / 2.0, (BOX_SIZE.y + choices_size.y) / 2.0, 0.3),
+                            visibility: Visibility::Hidden,
+                            ..default()
+                        },
+                        DialogueChoices
+                    )).with_children(|parent| {
+                        parent.spawn(SpriteBundle {
+                            sprite: Sprite {
+                                color: palette_band_colour(0),
+                                custom_size: Some(choices_size - Vec2::splat(BOX_BORDER * 2.0)),
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                            ..default()
+                        });
+
+                        parent.spawn((
+                            PixelTextBundle {
+                                text: PixelText::new(""),
+                                spatial: SpatialBundle::from_transform(Transform::from_xyz(BOX_PADDING.x / 2.0 - choices_size.x / 2.0, choices_size.y / 2.0 - BOX_PADDING.y / 2.0, 0.2))
+                            },
+                            DialogueChoicesText
+                        ));
+                    });
+                }
+            });
+        });
+
+        // Lock the player while they read.
+        for player_entity in &player_query {
+            commands.entity(player_entity).insert(InDialogue);
+        }
+    }
+}
+
+// Type out the text one letter at a time.
+fn reveal_dialogue(time: Res<Time>,
+                   mut dialogue_box_query: Query<&mut DialogueBox>,
+                   mut dialogue_text_query: Query<&mut PixelText, With<DialogueText>>) {
+    for mut dialogue_box in &mut dialogue_box_query {
+        dialogue_box.reveal_timer.tick(time.delta());
+
+        if !dialogue_box.page_finished() {
+            dialogue_box.chars_revealed += dialogue_box.reveal_timer.times_finished_this_tick() as usize;
+        }
+
+        // Show however much of the page we've got to.
+        let revealed: String = dialogue_box.current_page().chars().take(dialogue_box.chars_revealed).collect();
+        for mut text in &mut dialogue_text_query {
+            if text.text() != revealed {
+                text.set_text(revealed.clone());
+            }
+        }
+    }
+}
+
+// Show the choices once we get to them, and move the cursor up and down.
+fn choose_dialogue(mut action_input: ResMut<ActionInput>,
+                   mut dialogue_box_query: Query<&mut DialogueBox>,
+                   mut choices_query: Query<&mut Visibility, With<DialogueChoices>>,
+                   mut choices_text_query: Query<&mut PixelText, With<DialogueChoicesText>>) {
+    for mut dialogue_box in &mut dialogue_box_query {
+        let showing_choices = dialogue_box.showing_choices();
+        for mut visibility in &mut choices_query {
+            *visibility = if showing_choices { Visibility::Inherited } else { Visibility::Hidden };
+        }
+
+        if !showing_choices {
+            continue;
+        }
+
+        // Wrap around at either end.
+        let choice_count = dialogue_box.choices.len();
+        if action_input.just_pressed(CHOICE_UP_ACTION) {
+            dialogue_box.selected_choice = (dialogue_box.selected_choice + choice_count - 1) % choice_count;
+            action_input.consume(CHOICE_UP_ACTION);
+        }
+        if action_input.just_pressed(CHOICE_DOWN_ACTION) {
+            dialogue_box.selected_choice = (dialogue_box.selected_choice + 1) % choice_count;
+            action_input.consume(CHOICE_DOWN_ACTION);
+        }
+
+        // Draw the list with a cursor next to the selected choice.
+        let lines: Vec<String> = dialogue_box.choices.iter().enumerate()
+            .map(|(index, choice)| format!("{}{}", if index == dialogue_box.selected_choice { "> " } else { "  " }, choice))
+            .collect();
+        let choices_text = lines.join("\n");
+        for mut text in &mut choices_text_query {
+            if text.text() != choices_text {
+                text.set_text(choices_text.clone());
+            }
+        }
+    }
+}
+
+// Pressing the button either skips to the end of the page, moves on to the next page,
+// or closes the box if there are no more pages.
+pub(crate) fn advance_dialogue(mut commands: Commands,
+                               mut action_input: ResMut<ActionInput>,
+                               mut dialogue_closed_event_writer: EventWriter<DialogueClosedEvent>,
+                               mut dialogue_box_query: Query<(Entity, &mut DialogueBox)>,
+                               player_query: Query<Entity, (With<Player>, With<InDialogue>)>) {
+
+    if !action_input.just_pressed(ADVANCE_ACTION) || dialogue_box_query.is_empty() {
+        return;
+    }
+
+    // The press was for the box, don't let it start talking to someone again as soon as the box closes.
+    action_input.consume(ADVANCE_ACTION);
+
+    for (entity, mut dialogue_box) in &mut dialogue_box_query {
+        if !dialogue_box.page_finished() {
+            dialogue_box.chars_revealed = dialogue_box.current_page().chars().count();
+        } else if !dialogue_box.last_page() {
+            dialogue_box.page += 1;
+            dialogue_box.chars_revealed = 0;
+            dialogue_box.reveal_timer.reset();
+        } else {
+            // All done, close the box and let the player move again.
+            commands.entity(entity).despawn_recursive();
+            for player_entity in &player_query {
+                commands.entity(player_entity).remove::<InDialogue>();
+            }
+
+            dialogue_closed_event_writer.send(DialogueClosedEvent {
+                choice: if dialogue_box.choices.is_empty() { None } else { Some(dialogue_box.selected_choice) }
+            });
+        }
+    }
+}
+
+pub struct DialoguePlugin;
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OpenDialogueEvent>();
+        app.add_event::<DialogueClosedEvent>();
+
+        // The press that opens a dialogue shouldn't also advance it, and the press that closes
+        // it shouldn't start talking again, so advance before anything new gets opened.
+        app.add_systems(Update, (advance_dialogue, talk_to_dialogue, open_dialogue, reveal_dialogue, choose_dialogue)
+                                    .chain()
+                                    .after(crate::interaction::player_interact));
+    }
+}
@@ -0,0 +1,407 @@
+// Branching conversations loaded from .dialogue.ron files.
+// Writers describe a conversation as a set of named nodes. Each node has some text, optionally
+// some choices for the player, and some actions that happen when the node is reached.
+// Which node comes next can depend on the choice made or on the game flags.
+
+use std::collections::HashMap;
+
+use bevy::{asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext}, prelude::*};
+use bevy_ecs_ldtk::{prelude::LdtkFields, EntityInstance};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{dialogue::{DialogueClosedEvent, OpenDialogueEvent}, flags::{FlagCondition, FlagValue, GameFlags}, interaction::InteractEvent, items::GiveItemEvent, transition::TransitionStyle, warp::WarpRequestEvent};
+
+// Stop following nodes that don't show anything after this many, in case someone wrote a loop.
+const MAX_SILENT_NODES: usize = 64;
+
+// Something that happens when a node is reached.
+#[derive(Clone, Debug, Deserialize)]
+pub enum DialogueAction {
+    SetFlag(String, bool),
+    SetValue(String, FlagValue), // Set a flag to an int or string.
+    AddInt(String, i32), // Add to (or take away from) a counter.
+    GiveItem(String, u32), // Item id and how many.
+    Warp(String) // The entity iid of the WarpTarget to send the player to, once the conversation is over.
+}
+
+// A choice the player can pick at the end of a node.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub condition: Option<FlagCondition> // Only offered if this holds.
+}
+
+// Go to another node if the condition holds.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DialogueBranch {
+    pub condition: FlagCondition,
+    pub next: Option<String>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DialogueNode {
+    #[serde(default)]
+    pub text: Vec<String>,
+    #[serde(default)]
+    pub actions: Vec<DialogueAction>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    #[serde(default)]
+    pub branches: Vec<DialogueBranch>, // Checked in order when there are no choices.
+    #[serde(default)]
+    pub next: Option<String>, // Where to go if there are no choices and no branches match. None ends the conversation.
+    #[serde(default)]
+    pub transition: TransitionStyle // How the screen is covered if this node warps.
+}
+
+#[derive(Asset, TypePath, Clone, Debug, Deserialize)]
+pub struct DialogueGraph {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>
+}
+
+#[derive(Default)]
+struct DialogueGraphLoader;
+
+#[derive(Debug, Error)]
+enum DialogueGraphLoaderError {
+    #[error("Could not read dialogue file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse dialogue file: {0}")]
+    Ron(#[from] ron::error::SpannedError)
+}
+
+impl AssetLoader for DialogueGraphLoader {
+    type Asset = DialogueGraph;
+    type Settings = ();
+    type Error = DialogueGraphLoaderError;
+
+    async fn load<'a>(&'a self,
+                      reader: &'a mut Reader<'_>,
+                      _settings: &'a (),
+                      _load_context: &'a mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes::<DialogueGraph>(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dialogue.ron"]
+    }
+}
+
+// Talking to something with this component starts a conversation.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Conversation {
+    pub graph: Handle<DialogueGraph>
+}
+
+// Anything placed in LDtk with a DialogueFile has a full conversation to have, instead of its flat Dialogue.
+// Only those get a Conversation, so everything else still gets talked to the simple way.
+fn add_conversations(mut commands: Commands,
+                     asset_server: Res<AssetServer>,
+                     entity_instance_query: Query<(Entity, &EntityInstance), Added<EntityInstance>>) {
+    for (entity, entity_instance) in &entity_instance_query {
+        if let Ok(Some(dialogue_path)) = entity_instance.get_maybe_file_path_field("DialogueFile") {
+            commands.entity(entity).insert(Conversation {
+                graph: asset_server.load(dialogue_path)
+            });
+        }
+    }
+}
+
+// The conversation that's currently happening, if any.
+#[derive(Default, Resource)]
+struct ActiveConversation {
+    graph: Option<Handle<DialogueGraph>>,
+    node: Option<String>,
+    offered_choices: Vec<usize>, // Indices into the node's choices, for the ones that were actually shown.
+    interactor: Option<Entity>, // Who started the conversation. (and gets given any items)
+    pending_warp: Option<(String, TransitionStyle)> // Where to warp to once the last box has closed.
+}
+
+// The conversation's over, so now it's safe to go wherever it said to.
+fn end_conversation(active_conversation: &mut ActiveConversation,
+                    warp_request_event_writer: &mut EventWriter<WarpRequestEvent>) {
+    if let Some((target, transition)) = active_conversation.pending_warp.take() {
+        warp_request_event_writer.send(WarpRequestEvent {
+            target_entity_iid: bevy_ecs_ldtk::EntityIid::new(target),
+            transition
+        });
+    }
+    *active_conversation = ActiveConversation::default();
+}
+
+// Follow the conversation from the given node until we reach something that needs to be shown
+// to the player, or the conversation ends.
+fn run_conversation_from(mut node_id: Option<String>,
+                         graph_handle: &Handle<DialogueGraph>,
+                         graph: &DialogueGraph,
+                         active_conversation: &mut ActiveConversation,
+                         flags: &mut GameFlags,
+                         open_dialogue_event_writer: &mut EventWriter<OpenDialogueEvent>,
+                         warp_request_event_writer: &mut EventWriter<WarpRequestEvent>,
+                         give_item_event_writer: &mut EventWriter<GiveItemEvent>) {
+
+    for _ in 0..MAX_SILENT_NODES {
+        let Some(node) = node_id.as_ref().and_then(|id| graph.nodes.get(id)) else {
+            if let Some(id) = &node_id {
+                println!("Dialogue node {} doesn't exist!", id);
+            }
+            break;
+        };
+
+        // Do whatever this node does.
+        for action in &node.actions {
+            match action {
+                DialogueAction::SetFlag(name, value) => flags.set(name, *value),
+                DialogueAction::SetValue(name, value) => flags.set_value(name, value.clone()),
+                DialogueAction::AddInt(name, amount) => flags.add_int(name, *amount),
+                DialogueAction::GiveItem(item, count) => {
+                    if let Some(interactor) = active_conversation.interactor {
+                        give_item_event_writer.send(GiveItemEvent {
+                            entity: interactor,
+                            item: item.clone(),
+                            count: *count
+                        });
+                    }
+                },
+                // Warping straight away would whisk the player off with the box still open.
+                DialogueAction::Warp(target) => {
+                    active_conversation.pending_warp = Some((target.clone(), node.transition));
+                }
+            }
+        }
+
+        let offered_choices = offered_choices(node, flags);
+
+        // Something to show, so stop here and wait for the box to close.
+        if !node.text.is_empty() || !offered_choices.is_empty() {
+            open_dialogue_event_writer.send(OpenDialogueEvent {
+                text: node.text.clone(),
+                choices: offered_choices.iter().map(|index| node.choices[*index].text.clone()).collect()
+            });
+
+            active_conversation.graph = Some(graph_handle.clone());
+            active_conversation.node = node_id;
+            active_conversation.offered_choices = offered_choices;
+            return;
+        }
+
+        node_id = next_node(node, flags);
+    }
+
+    // Nothing more to say.
+    end_conversation(active_conversation, warp_request_event_writer);
+}
+
+// Which of a node's choices are available right now, as indices into its choices.
+fn offered_choices(node: &DialogueNode, flags: &GameFlags) -> Vec<usize> {
+    node.choices.iter().enumerate()
+        .filter(|(_, choice)| choice.condition.as_ref().is_none_or(|condition| condition.evaluate(flags)))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+// Where to go after a node when there was no choice to be made.
+fn next_node(node: &DialogueNode, flags: &GameFlags) -> Option<String> {
+    for branch in &node.branches {
+        if branch.condition.evaluate(flags) {
+            return branch.next.clone();
+        }
+    }
+
+    node.next.clone()
+}
+
+fn start_conversation(mut interact_event_reader: EventReader<InteractEvent>,
+                      mut active_conversation: ResMut<ActiveConversation>,
+                      mut flags: ResMut<GameFlags>,
+                      mut open_dialogue_event_writer: EventWriter<OpenDialogueEvent>,
+                      mut warp_request_event_writer: EventWriter<WarpRequestEvent>,
+                      mut give_item_event_writer: EventWriter<GiveItemEvent>,
+                      conversation_query: Query<&Conversation>,
+                      dialogue_graphs: Res<Assets<DialogueGraph>>) {
+    for event in interact_event_reader.read() {
+        if active_conversation.graph.is_some() {
+            continue;
+        }
+
+        if let Ok(conversation) = conversation_query.get(event.target) {
+            if let Some(graph) = dialogue_graphs.get(&conversation.graph) {
+                active_conversation.interactor = Some(event.interactor);
+                run_conversation_from(Some(graph.start.clone()), &conversation.graph, graph, &mut active_conversation,
+                                      &mut flags, &mut open_dialogue_event_writer, &mut warp_request_event_writer, &mut give_item_event_writer);
+            }
+        }
+    }
+}
+
+// When the player closes a text box that belongs to a conversation, move on to the next node.
+fn continue_conversation(mut dialogue_closed_event_reader: EventReader<DialogueClosedEvent>,
+                         mut active_conversation: ResMut<ActiveConversation>,
+                         mut flags: ResMut<GameFlags>,
+                         mut open_dialogue_event_writer: EventWriter<OpenDialogueEvent>,
+                         mut warp_request_event_writer: EventWriter<WarpRequestEvent>,
+                         mut give_item_event_writer: EventWriter<GiveItemEvent>,
+                         dialogue_graphs: Res<Assets<DialogueGraph>>) {
+    for event in dialogue_closed_event_reader.read() {
+        let (Some(graph_handle), Some(node_id)) = (active_conversation.graph.clone(), active_conversation.node.clone()) else {
+            continue;
+        };
+
+        let Some(graph) = dialogue_graphs.get(&graph_handle) else {
+            end_conversation(&mut active_conversation, &mut warp_request_event_writer);
+            continue;
+        };
+
+        let Some(node) = graph.nodes.get(&node_id) else {
+            end_conversation(&mut active_conversation, &mut warp_request_event_writer);
+            continue;
+        };
+
+        // Follow the choice if there was one, otherwise carry on as normal.
+        let next = match event.choice.and_then(|choice| active_conversation.offered_choices.get(choice)) {
+            Some(choice_index) => node.choices[*choice_index].next.clone(),
+            None => next_node(node, &flags)
+        };
+
+        run_conversation_from(next, &graph_handle, graph, &mut active_conversation,
+                              &mut flags, &mut open_dialogue_event_writer, &mut warp_request_event_writer, &mut give_item_event_writer);
+    }
+}
+
+pub struct DialogueGraphPlugin;
+impl Plugin for DialogueGraphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<DialogueGraph>();
+        app.init_asset_loader::<DialogueGraphLoader>();
+        app.init_resource::<ActiveConversation>();
+
+        app.add_systems(Update, add_conversations.before(start_conversation));
+
+        // Needs to happen after a box closes but before new ones are opened, so that
+        // one page of a conversation leads straight into the next.
+        app.add_systems(Update, (continue_conversation, start_conversation)
+                                    .after(crate::dialogue::advance_dialogue)
+                                    .before(crate::dialogue::open_dialogue));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+
+    type ConversationEventWriters = (EventWriter<'static, OpenDialogueEvent>, EventWriter<'static, WarpRequestEvent>, EventWriter<'static, GiveItemEvent>);
+
+    // Somewhere for a conversation to send its events, without the rest of the game.
+    struct TestConversation {
+        world: World,
+        event_writers: SystemState<ConversationEventWriters>,
+        active: ActiveConversation,
+        flags: GameFlags
+    }
+
+    impl TestConversation {
+        fn new() -> Self {
+            let mut world = World::new();
+            world.init_resource::<Events<OpenDialogueEvent>>();
+            world.init_resource::<Events<WarpRequestEvent>>();
+            world.init_resource::<Events<GiveItemEvent>>();
+            let event_writers = SystemState::new(&mut world);
+            Self { world, event_writers, active: ActiveConversation::default(), flags: GameFlags::default() }
+        }
+
+        fn start(&mut self, graph: &DialogueGraph) {
+            let (mut open_dialogue_event_writer, mut warp_request_event_writer, mut give_item_event_writer) = self.event_writers.get_mut(&mut self.world);
+            run_conversation_from(Some(graph.start.clone()), &Handle::default(), graph, &mut self.active, &mut self.flags,
+                                  &mut open_dialogue_event_writer, &mut warp_request_event_writer, &mut give_item_event_writer);
+        }
+
+        fn end(&mut self) {
+            let (_, mut warp_request_event_writer, _) = self.event_writers.get_mut(&mut self.world);
+            end_conversation(&mut self.active, &mut warp_request_event_writer);
+        }
+
+        fn opened(&mut self) -> Vec<OpenDialogueEvent> {
+            self.world.resource_mut::<Events<OpenDialogueEvent>>().drain().collect()
+        }
+
+        fn warps(&mut self) -> Vec<WarpRequestEvent> {
+            self.world.resource_mut::<Events<WarpRequestEvent>>().drain().collect()
+        }
+    }
+
+    fn graph(text: &str) -> DialogueGraph {
+        ron::de::from_str(text).unwrap()
+    }
+
+    fn node(text: &str) -> DialogueNode {
+        ron::de::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn the_first_matching_branch_wins() {
+        let node = node(r#"(
+            branches: [(condition: Flag("a"), next: Some("a")), (condition: Flag("b"), next: Some("b"))],
+            next: Some("neither"),
+        )"#);
+
+        let mut flags = GameFlags::default();
+        assert_eq!(next_node(&node, &flags).as_deref(), Some("neither"));
+        flags.set("b", true);
+        assert_eq!(next_node(&node, &flags).as_deref(), Some("b"));
+        flags.set("a", true);
+        assert_eq!(next_node(&node, &flags).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn choices_are_only_offered_when_their_condition_holds() {
+        let node = node(r#"(
+            choices: [
+                (text: "Always"),
+                (text: "Secret", condition: Some(Flag("knows_secret"))),
+                (text: "Bye"),
+            ],
+        )"#);
+
+        let mut flags = GameFlags::default();
+        assert_eq!(offered_choices(&node, &flags), vec![0, 2]);
+        flags.set("knows_secret", true);
+        assert_eq!(offered_choices(&node, &flags), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn silent_loops_give_up() {
+        let graph = graph(r#"(start: "a", nodes: {"a": (next: Some("b")), "b": (actions: [AddInt("laps", 1)], next: Some("a"))})"#);
+        let mut conversation = TestConversation::new();
+        conversation.start(&graph);
+
+        assert!(conversation.opened().is_empty());
+        assert_eq!(conversation.flags.get_int("laps") as usize, MAX_SILENT_NODES / 2);
+        assert!(conversation.active.graph.is_none());
+    }
+
+    #[test]
+    fn warps_wait_for_the_conversation_to_end() {
+        let graph = graph(r#"(start: "bye", nodes: {"bye": (text: ["Off you go!"], actions: [Warp("somewhere")], transition: Wipe)})"#);
+        let mut conversation = TestConversation::new();
+
+        // The box is still open, so no warping yet.
+        conversation.start(&graph);
+        assert_eq!(conversation.opened().len(), 1);
+        assert!(conversation.warps().is_empty());
+
+        conversation.end();
+        let warps = conversation.warps();
+        assert_eq!(warps.len(), 1);
+        assert_eq!(warps[0].target_entity_iid.as_str(), "somewhere");
+        assert_eq!(warps[0].transition, TransitionStyle::Wipe);
+    }
+}
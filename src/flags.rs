@@ -0,0 +1,257 @@
+// Global story flags. Things like "talked to the mayor" or "opened the gate" that other
+// parts of the game can check to decide what should happen.
+// Flags can also hold numbers and strings, for counting things or remembering choices.
+// They're saved along with everything else, and LDtk entities can be gated on them with a FlagCondition.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// The value of a flag. Most flags are just on or off, but counters and names are handy too.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FlagValue {
+    Bool(bool),
+    Int(i32),
+    String(String)
+}
+
+impl FlagValue {
+    // Whether the value counts as "set". Zero and empty strings don't.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            FlagValue::Bool(value) => *value,
+            FlagValue::Int(value) => *value != 0,
+            FlagValue::String(value) => !value.is_empty()
+        }
+    }
+
+    // Read a value written in LDtk or a dialogue file. Numbers and true/false get their own type, anything else is a string.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        if let Ok(value) = text.parse::<bool>() {
+            FlagValue::Bool(value)
+        } else if let Ok(value) = text.parse::<i32>() {
+            FlagValue::Int(value)
+        } else {
+            FlagValue::String(text.trim_matches('"').to_string())
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug, Resource, Serialize, Deserialize)]
+pub struct GameFlags {
+    flags: HashMap<String, FlagValue>
+}
+
+impl GameFlags {
+    // Flags that have never been set are false.
+    pub fn get(&self, name: &str) -> bool {
+        self.flags.get(name).is_some_and(|value| value.is_truthy())
+    }
+
+    // Ints that have never been set are 0.
+    pub fn get_int(&self, name: &str) -> i32 {
+        match self.flags.get(name) {
+            Some(FlagValue::Int(value)) => *value,
+            Some(FlagValue::Bool(value)) => *value as i32,
+            _ => 0
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match self.flags.get(name) {
+            Some(FlagValue::String(value)) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<&FlagValue> {
+        self.flags.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) {
+        self.set_value(name, FlagValue::Bool(value));
+    }
+
+    pub fn set_int(&mut self, name: &str, value: i32) {
+        self.set_value(name, FlagValue::Int(value));
+    }
+
+    pub fn set_string(&mut self, name: &str, value: &str) {
+        self.set_value(name, FlagValue::String(value.to_string()));
+    }
+
+    pub fn set_value(&mut self, name: &str, value: FlagValue) {
+        self.flags.insert(name.to_string(), value);
+    }
+
+    // Add to a counter. (which can be negative to take away)
+    pub fn add_int(&mut self, name: &str, amount: i32) {
+        let value = self.get_int(name) + amount;
+        self.set_int(name, value);
+    }
+}
+
+// Something that can be checked against the game flags.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum FlagCondition {
+    Flag(String), // The flag is set.
+    NotFlag(String), // The flag isn't set.
+    Equals(String, FlagValue), // The flag has exactly this value.
+    NotEquals(String, FlagValue), // The flag has any other value (or none).
+    AtLeast(String, i32), // The flag is an int at least this big.
+    GreaterThan(String, i32), // The flag is an int bigger than this.
+    AtMost(String, i32), // The flag is an int no bigger than this.
+    LessThan(String, i32), // The flag is an int smaller than this.
+    All(Vec<FlagCondition>), // Every condition holds.
+    Any(Vec<FlagCondition>) // At least one condition holds.
+}
+
+impl FlagCondition {
+    pub fn evaluate(&self, flags: &GameFlags) -> bool {
+        match self {
+            FlagCondition::Flag(name) => flags.get(name),
+            FlagCondition::NotFlag(name) => !flags.get(name),
+            FlagCondition::Equals(name, value) => flags.get_value(name) == Some(value),
+            FlagCondition::NotEquals(name, value) => flags.get_value(name) != Some(value),
+            FlagCondition::AtLeast(name, value) => flags.get_int(name) >= *value,
+            FlagCondition::GreaterThan(name, value) => flags.get_int(name) > *value,
+            FlagCondition::AtMost(name, value) => flags.get_int(name) <= *value,
+            FlagCondition::LessThan(name, value) => flags.get_int(name) < *value,
+            FlagCondition::All(conditions) => conditions.iter().all(|condition| condition.evaluate(flags)),
+            FlagCondition::Any(conditions) => conditions.iter().any(|condition| condition.evaluate(flags))
+        }
+    }
+
+    // Read a condition written in an LDtk field, which are kept short so they're easy to type in the editor.
+    // e.g. "opened_gate", "!met_mayor", "badges >= 2", "coins < 10", "starter == fire", "met_mayor && !opened_gate"
+    // && binds tighter than ||, and there are no brackets.
+    pub fn parse(text: &str) -> Result<Self, FlagConditionParseError> {
+        let any = text.split("||").map(|all_text| {
+            let all = all_text.split("&&").map(Self::parse_single).collect::<Result<Vec<_>, _>>()?;
+            Ok(if all.len() == 1 { all.into_iter().next().unwrap() } else { FlagCondition::All(all) })
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(if any.len() == 1 { any.into_iter().next().unwrap() } else { FlagCondition::Any(any) })
+    }
+
+    fn parse_single(text: &str) -> Result<Self, FlagConditionParseError> {
+        let text = text.trim();
+
+        // Longer operators first so ">=" doesn't get read as ">".
+        for operator in ["==", "!=", ">=", "<=", ">", "<"] {
+            if let Some((name, value)) = text.split_once(operator) {
+                let name = Self::parse_name(name, text)?;
+                let value = FlagValue::parse(value);
+                return match (operator, value) {
+                    ("==", value) => Ok(FlagCondition::Equals(name, value)),
+                    ("!=", value) => Ok(FlagCondition::NotEquals(name, value)),
+                    (">=", FlagValue::Int(value)) => Ok(FlagCondition::AtLeast(name, value)),
+                    ("<=", FlagValue::Int(value)) => Ok(FlagCondition::AtMost(name, value)),
+                    (">", FlagValue::Int(value)) => Ok(FlagCondition::GreaterThan(name, value)),
+                    ("<", FlagValue::Int(value)) => Ok(FlagCondition::LessThan(name, value)),
+                    _ => Err(FlagConditionParseError::NotANumber(text.to_string()))
+                };
+            }
+        }
+
+        match text.strip_prefix('!') {
+            Some(name) => Ok(FlagCondition::NotFlag(Self::parse_name(name, text)?)),
+            None => Ok(FlagCondition::Flag(Self::parse_name(text, text)?))
+        }
+    }
+
+    fn parse_name(name: &str, text: &str) -> Result<String, FlagConditionParseError> {
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(FlagConditionParseError::BadName(text.to_string()));
+        }
+        Ok(name.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq, Hash)]
+pub enum FlagConditionParseError {
+    #[error("Expected a flag name in \"{0}\"")]
+    BadName(String),
+    #[error("Can only compare numbers with >, >=, < and <= in \"{0}\"")]
+    NotANumber(String)
+}
+
+pub struct FlagsPlugin;
+impl Plugin for FlagsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameFlags>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> FlagCondition {
+        FlagCondition::parse(text).unwrap()
+    }
+
+    fn flag(name: &str) -> FlagCondition {
+        FlagCondition::Flag(name.to_string())
+    }
+
+    #[test]
+    fn parses_each_operator() {
+        assert_eq!(parse("opened_gate"), flag("opened_gate"));
+        assert_eq!(parse("!met_mayor"), FlagCondition::NotFlag(String::from("met_mayor")));
+        assert_eq!(parse("starter == fire"), FlagCondition::Equals(String::from("starter"), FlagValue::String(String::from("fire"))));
+        assert_eq!(parse("coins != 0"), FlagCondition::NotEquals(String::from("coins"), FlagValue::Int(0)));
+        assert_eq!(parse("badges >= 2"), FlagCondition::AtLeast(String::from("badges"), 2));
+        assert_eq!(parse("badges > 2"), FlagCondition::GreaterThan(String::from("badges"), 2));
+        assert_eq!(parse("coins <= 10"), FlagCondition::AtMost(String::from("coins"), 10));
+        assert_eq!(parse("coins < 10"), FlagCondition::LessThan(String::from("coins"), 10));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(parse("a || b && c"), FlagCondition::Any(vec![flag("a"), FlagCondition::All(vec![flag("b"), flag("c")])]));
+        assert_eq!(parse("a && b || c"), FlagCondition::Any(vec![FlagCondition::All(vec![flag("a"), flag("b")]), flag("c")]));
+
+        let mut flags = GameFlags::default();
+        flags.set("a", true);
+        assert!(parse("a || b && c").evaluate(&flags));
+        assert!(!parse("a && b || c").evaluate(&flags));
+    }
+
+    #[test]
+    fn negation_only_applies_to_its_own_flag() {
+        let condition = parse("!met_mayor && opened_gate");
+        assert_eq!(condition, FlagCondition::All(vec![FlagCondition::NotFlag(String::from("met_mayor")), flag("opened_gate")]));
+
+        let mut flags = GameFlags::default();
+        flags.set("opened_gate", true);
+        assert!(condition.evaluate(&flags));
+        flags.set("met_mayor", true);
+        assert!(!condition.evaluate(&flags));
+    }
+
+    #[test]
+    fn comparisons_treat_unset_flags_as_zero() {
+        let mut flags = GameFlags::default();
+        assert!(parse("coins < 10").evaluate(&flags));
+        assert!(!parse("badges >= 2").evaluate(&flags));
+
+        flags.add_int("badges", 2);
+        assert!(parse("badges >= 2").evaluate(&flags));
+        assert!(!parse("badges > 2").evaluate(&flags));
+    }
+
+    #[test]
+    fn malformed_conditions_are_errors() {
+        assert_eq!(FlagCondition::parse("").unwrap_err(), FlagConditionParseError::BadName(String::new()));
+        assert_eq!(FlagCondition::parse("&& a").unwrap_err(), FlagConditionParseError::BadName(String::new()));
+        assert_eq!(FlagCondition::parse("a ||").unwrap_err(), FlagConditionParseError::BadName(String::new()));
+        assert_eq!(FlagCondition::parse(">= 2").unwrap_err(), FlagConditionParseError::BadName(String::from(">= 2")));
+        assert_eq!(FlagCondition::parse("met mayor").unwrap_err(), FlagConditionParseError::BadName(String::from("met mayor")));
+        assert_eq!(FlagCondition::parse("badges >= lots").unwrap_err(), FlagConditionParseError::NotANumber(String::from("badges >= lots")));
+    }
+}
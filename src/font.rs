@@ -0,0 +1,363 @@
+// Pixel perfect text, drawn from a tile sheet of glyphs.
+// Bevy's text is anti-aliased, which turns into a mess of noisy edges once the palette shader squashes it
+// down to 4 colours. Instead, each letter is a sprite sat exactly on the pixel grid, tinted one of the 4 grey bands.
+
+use std::collections::HashMap;
+
+use bevy::{asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext}, prelude::*, render::view::VisibilitySystems, sprite::Anchor, transform::TransformSystem};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::post_process::palette_band_colour;
+
+const DEFAULT_FONT_PATH: &str = "default.font.ron";
+
+// What a .font.ron file looks like.
+#[derive(Deserialize)]
+struct BitmapFontFile {
+    image: String, // Relative to the font file.
+    glyph_width: u32,
+    glyph_height: u32,
+    columns: u32,
+    glyphs: String, // Every character in the sheet, in order.
+    advance: u32, // How far along to move after each glyph.
+    line_height: u32,
+    fallback: char // Drawn for anything that isn't in the sheet.
+}
+
+#[derive(Asset, TypePath, Debug)]
+pub struct BitmapFont {
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+    glyphs: HashMap<char, usize>,
+    fallback: usize,
+    pub advance: u32,
+    pub line_height: u32
+}
+
+impl BitmapFont {
+    // Where a character is in the sheet.
+    pub fn glyph_index(&self, c: char) -> usize {
+        self.glyphs.get(&c).copied().unwrap_or(self.fallback)
+    }
+
+    // How wide a single line of text is, in pixels.
+    pub fn measure(&self, text: &str) -> u32 {
+        text.chars().count() as u32 * self.advance
+    }
+
+    // Break some glyphs up into lines no wider than max_width (if there is one).
+    // Lines are broken between words where possible, and words that are too long on their own are split wherever they need to be.
+    fn wrap(&self, glyphs: &[(char, u8)], max_width: Option<u32>) -> Vec<Vec<(char, u8)>> {
+        let max_width = max_width.unwrap_or(u32::MAX);
+        let mut lines = vec![Vec::new()];
+        let mut line_width = 0;
+
+        for chunk in glyphs.split_inclusive(|(c, _)| *c == ' ' || *c == '\n') {
+            let (word, separator) = match chunk.split_last() {
+                Some((last, word)) if last.0 == ' ' || last.0 == '\n' => (word, Some(last)),
+                _ => (chunk, None)
+            };
+
+            // Move the whole word down if it doesn't fit on what's left of this line.
+            let word_width = word.len() as u32 * self.advance;
+            if line_width > 0 && line_width + word_width > max_width {
+                lines.push(Vec::new());
+                line_width = 0;
+            }
+
+            for glyph in word {
+                if line_width > 0 && line_width + self.advance > max_width {
+                    lines.push(Vec::new());
+                    line_width = 0;
+                }
+                lines.last_mut().unwrap().push(*glyph);
+                line_width += self.advance;
+            }
+
+            match separator {
+                Some(('\n', _)) => {
+                    lines.push(Vec::new());
+                    line_width = 0;
+                },
+                // Spaces that would hang off the end of a line are dropped.
+                Some(space) if line_width + self.advance <= max_width => {
+                    lines.last_mut().unwrap().push(*space);
+                    line_width += self.advance;
+                },
+                _ => {}
+            }
+        }
+
+        lines
+    }
+}
+
+#[derive(Default)]
+struct BitmapFontLoader;
+
+#[derive(Debug, Error)]
+enum BitmapFontLoaderError {
+    #[error("Could not read font file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse font file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("Font fallback glyph {0:?} isn't in the font")]
+    MissingFallback(char),
+    #[error("Font image needs at least one column of glyphs")]
+    BadColumns
+}
+
+impl AssetLoader for BitmapFontLoader {
+    type Asset = BitmapFont;
+    type Settings = ();
+    type Error = BitmapFontLoaderError;
+
+    async fn load<'a>(&'a self,
+                      reader: &'a mut Reader<'_>,
+                      _settings: &'a (),
+                      load_context: &'a mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file = ron::de::from_bytes::<BitmapFontFile>(&bytes)?;
+
+        let glyphs: HashMap<char, usize> = file.glyphs.chars().enumerate().map(|(index, c)| (c, index)).collect();
+        let fallback = *glyphs.get(&file.fallback).ok_or(BitmapFontLoaderError::MissingFallback(file.fallback))?;
+        if file.columns == 0 {
+            return Err(BitmapFontLoaderError::BadColumns);
+        }
+
+        // The image lives next to the font file.
+        let image_path = load_context.path().parent().map_or(file.image.clone().into(), |parent| parent.join(&file.image));
+        let image = load_context.load(image_path);
+
+        let rows = (glyphs.len() as u32).div_ceil(file.columns);
+        let layout = load_context.add_labeled_asset(String::from("layout"),
+            TextureAtlasLayout::from_grid(UVec2::new(file.glyph_width, file.glyph_height), file.columns, rows, None, None));
+
+        Ok(BitmapFont {
+            image,
+            layout,
+            glyphs,
+            fallback,
+            advance: file.advance,
+            line_height: file.line_height
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["font.ron"]
+    }
+}
+
+// The font used by any text that doesn't ask for a different one.
+#[derive(Default, Resource)]
+pub struct DefaultBitmapFont(pub Handle<BitmapFont>);
+
+fn load_default_font(mut default_font: ResMut<DefaultBitmapFont>, asset_server: Res<AssetServer>) {
+    default_font.0 = asset_server.load(DEFAULT_FONT_PATH);
+}
+
+// A run of text all in the same colour.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PixelTextSection {
+    pub text: String,
+    pub colour: u8 // Which of the 4 palette bands to draw in, 0 (lightest) to 3 (darkest).
+}
+
+// Some text, laid out from the top left corner of the entity.
+#[derive(Clone, Debug, Default, PartialEq, Component)]
+pub struct PixelText {
+    pub sections: Vec<PixelTextSection>,
+    pub max_width: Option<u32>, // Wrap lines to fit in this many pixels.
+    pub font: Option<Handle<BitmapFont>> // The default font if there isn't one.
+}
+
+impl PixelText {
+    // Text all in the darkest colour, which is what it usually is.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self::coloured(text, 3)
+    }
+
+    pub fn coloured(text: impl Into<String>, colour: u8) -> Self {
+        Self {
+            sections: vec![PixelTextSection { text: text.into(), colour }],
+            ..default()
+        }
+    }
+
+    pub fn with_max_width(mut self, max_width: u32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    // Everything, without the colours.
+    pub fn text(&self) -> String {
+        self.sections.iter().map(|section| section.text.as_str()).collect()
+    }
+
+    // Every character, along with the colour it's drawn in.
+    fn glyphs(&self) -> Vec<(char, u8)> {
+        self.sections.iter()
+            .flat_map(|section| section.text.chars().map(|c| (c, section.colour)))
+            .collect()
+    }
+
+    // Replace all of the text, keeping the colour of the first section.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        let colour = self.sections.first().map_or(3, |section| section.colour);
+        self.sections = vec![PixelTextSection { text: text.into(), colour }];
+    }
+}
+
+#[derive(Bundle, Default)]
+pub struct PixelTextBundle {
+    pub text: PixelText,
+    pub spatial: SpatialBundle
+}
+
+// One letter of some PixelText.
+#[derive(Component)]
+struct PixelGlyph;
+
+// Lay out the glyphs again whenever the text changes, or its font finishes loading.
+fn layout_pixel_text(mut commands: Commands,
+                     mut font_events: EventReader<AssetEvent<BitmapFont>>,
+                     default_font: Res<DefaultBitmapFont>,
+                     fonts: Res<Assets<BitmapFont>>,
+                     text_query: Query<(Entity, Ref<PixelText>, Option<&Children>)>,
+                     glyph_query: Query<(), With<PixelGlyph>>) {
+    let loaded_fonts: Vec<AssetId<BitmapFont>> = font_events.read().filter_map(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+        _ => None
+    }).collect();
+
+    for (entity, pixel_text, children) in &text_query {
+        let font_handle = pixel_text.font.as_ref().unwrap_or(&default_font.0);
+        if !pixel_text.is_changed() && !loaded_fonts.contains(&font_handle.id()) {
+            continue;
+        }
+
+        // Out with the old letters.
+        for child in children.into_iter().flatten() {
+            if glyph_query.contains(*child) {
+                commands.entity(*child).despawn();
+            }
+        }
+
+        // Try again once it's loaded.
+        let Some(font) = fonts.get(font_handle) else {
+            continue;
+        };
+
+        let glyphs = pixel_text.glyphs();
+
+        commands.entity(entity).with_children(|parent| {
+            for (line_index, line) in font.wrap(&glyphs, pixel_text.max_width).iter().enumerate() {
+                for (column, (c, colour)) in line.iter().enumerate() {
+                    if *c == ' ' {
+                        continue;
+                    }
+
+                    // Everything lands on whole pixels, counting down from the top left.
+                    let x = column as u32 * font.advance;
+                    let y = line_index as u32 * font.line_height;
+                    parent.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: palette_band_colour(*colour as usize),
+                                anchor: Anchor::TopLeft,
+                                ..default()
+                            },
+                            texture: font.image.clone(),
+                            transform: Transform::from_xyz(x as f32, -(y as f32), 0.0),
+                            ..default()
+                        },
+                        TextureAtlas {
+                            layout: font.layout.clone(),
+                            index: font.glyph_index(*c)
+                        },
+                        PixelGlyph
+                    ));
+                }
+            }
+        });
+    }
+}
+
+pub struct FontPlugin;
+impl Plugin for FontPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<BitmapFont>();
+        app.init_asset_loader::<BitmapFontLoader>();
+        app.init_resource::<DefaultBitmapFont>();
+
+        app.add_systems(Startup, load_default_font);
+
+        // After everything in Update has had a chance to change some text, but before it's drawn.
+        app.add_systems(PostUpdate, layout_pixel_text
+                                        .before(TransformSystem::TransformPropagate)
+                                        .before(VisibilitySystems::VisibilityPropagate));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every glyph is a single pixel wide, so widths are just counting characters.
+    fn font() -> BitmapFont {
+        BitmapFont {
+            image: Handle::default(),
+            layout: Handle::default(),
+            glyphs: HashMap::new(),
+            fallback: 0,
+            advance: 1,
+            line_height: 1
+        }
+    }
+
+    fn wrap(text: &str, max_width: Option<u32>) -> Vec<String> {
+        font().wrap(&PixelText::new(text).glyphs(), max_width).iter()
+            .map(|line| line.iter().map(|(c, _)| *c).collect())
+            .collect()
+    }
+
+    #[test]
+    fn words_that_dont_fit_move_to_the_next_line() {
+        assert_eq!(wrap("hi there", Some(5)), vec!["hi ", "there"]);
+        assert_eq!(wrap("hi there", None), vec!["hi there"]);
+    }
+
+    #[test]
+    fn words_longer_than_a_line_are_split() {
+        assert_eq!(wrap("abcdefghijkl", Some(5)), vec!["abcde", "fghij", "kl"]);
+        assert_eq!(wrap("a abcdefg", Some(5)), vec!["a ", "abcde", "fg"]);
+    }
+
+    #[test]
+    fn newlines_always_start_a_new_line() {
+        assert_eq!(wrap("ab\ncd", None), vec!["ab", "cd"]);
+        assert_eq!(wrap("ab\n\ncd", Some(5)), vec!["ab", "", "cd"]);
+    }
+
+    #[test]
+    fn spaces_at_the_end_of_a_full_line_are_dropped() {
+        assert_eq!(wrap("hello world", Some(5)), vec!["hello", "world"]);
+    }
+
+    #[test]
+    fn each_glyph_keeps_its_sections_colour() {
+        let pixel_text = PixelText {
+            sections: (0..4).map(|colour| PixelTextSection { text: format!("{} ", colour), colour }).collect(),
+            ..default()
+        };
+
+        let lines = font().wrap(&pixel_text.glyphs(), Some(4));
+        assert_eq!(lines, vec![vec![('0', 0), (' ', 0), ('1', 1), (' ', 1)], vec![('2', 2), (' ', 2), ('3', 3), (' ', 3)]]);
+
+        // Each colour is its own band of the palette, lightest first.
+        let greys: Vec<f32> = (0..4).map(|colour| palette_band_colour(colour).to_linear().red).collect();
+        assert!(greys.windows(2).all(|pair| pair[0] > pair[1]), "{:?}", greys);
+    }
+}
@@ -0,0 +1,298 @@
+// Turn keyboard and gamepad input into the handful of buttons the game actually cares about.
+// Everything else in the game should ask ActionInput about Actions rather than reading keys directly,
+// that way players can rebind things and use a gamepad without every system having to know about it.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+// Where players can rebind their buttons. Loaded once at startup, if it's missing we just use the defaults.
+pub const INPUT_CONFIG_PATH: &str = "config/input.ron";
+
+// The buttons on our imaginary handheld.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [Action::Up, Action::Down, Action::Left, Action::Right, Action::A, Action::B, Action::Start, Action::Select];
+
+    pub fn is_direction(&self) -> bool {
+        matches!(self, Action::Up | Action::Down | Action::Left | Action::Right)
+    }
+}
+
+// Which keys and gamepad buttons trigger which actions.
+#[derive(Clone, Debug, Resource, Serialize, Deserialize)]
+pub struct InputBindings {
+    #[serde(default)]
+    pub keyboard: HashMap<Action, Vec<KeyCode>>,
+    #[serde(default)]
+    pub gamepad: HashMap<Action, Vec<GamepadButtonType>>,
+    #[serde(default = "default_stick_deadzone")]
+    pub stick_deadzone: f32, // How far the left stick needs to be pushed to count as a direction.
+    #[serde(default = "default_buffer_window")]
+    pub buffer_window: f32, // How long (in seconds) a press is remembered for if it can't be acted on straight away.
+    #[serde(default = "default_turn_in_place")]
+    pub turn_in_place: bool // Tapping a new direction while standing still just turns to face it.
+}
+
+fn default_stick_deadzone() -> f32 {
+    0.5
+}
+
+fn default_buffer_window() -> f32 {
+    0.15
+}
+
+fn default_turn_in_place() -> bool {
+    true
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            keyboard: HashMap::from([
+                (Action::Up, vec![KeyCode::ArrowUp]),
+                (Action::Down, vec![KeyCode::ArrowDown]),
+                (Action::Left, vec![KeyCode::ArrowLeft]),
+                (Action::Right, vec![KeyCode::ArrowRight]),
+                (Action::A, vec![KeyCode::KeyZ]),
+                (Action::B, vec![KeyCode::KeyX]),
+                (Action::Start, vec![KeyCode::Enter]),
+                (Action::Select, vec![KeyCode::ShiftRight])
+            ]),
+            gamepad: HashMap::from([
+                (Action::Up, vec![GamepadButtonType::DPadUp]),
+                (Action::Down, vec![GamepadButtonType::DPadDown]),
+                (Action::Left, vec![GamepadButtonType::DPadLeft]),
+                (Action::Right, vec![GamepadButtonType::DPadRight]),
+                (Action::A, vec![GamepadButtonType::South]),
+                (Action::B, vec![GamepadButtonType::East]),
+                (Action::Start, vec![GamepadButtonType::Start]),
+                (Action::Select, vec![GamepadButtonType::Select])
+            ]),
+            stick_deadzone: default_stick_deadzone(),
+            buffer_window: default_buffer_window(),
+            turn_in_place: default_turn_in_place()
+        }
+    }
+}
+
+impl InputBindings {
+    // Read the bindings from the config file, falling back to the defaults for anything that isn't there.
+    pub fn load(path: &str) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => {
+                println!("No input config at {}, using the default bindings.", path);
+                return Self::default();
+            }
+        };
+
+        match ron::de::from_str::<InputBindings>(&text) {
+            Ok(mut bindings) => {
+                // Anything left out of the file keeps its default binding.
+                let defaults = Self::default();
+                for action in Action::ALL {
+                    bindings.keyboard.entry(action).or_insert_with(|| defaults.keyboard[&action].clone());
+                    bindings.gamepad.entry(action).or_insert_with(|| defaults.gamepad[&action].clone());
+                }
+                bindings
+            },
+            Err(error) => {
+                println!("Couldn't read input config {}: {}. Using the default bindings.", path, error);
+                Self::default()
+            }
+        }
+    }
+
+    // Make a key the only one that triggers an action.
+    // A key can only do one thing, so if another action already uses it nothing changes and that action is handed back.
+    pub fn rebind_key(&mut self, action: Action, key: KeyCode) -> Result<(), Action> {
+        let taken_by = Action::ALL.into_iter()
+            .find(|other| *other != action && self.keyboard.get(other).is_some_and(|keys| keys.contains(&key)));
+        if let Some(other) = taken_by {
+            return Err(other);
+        }
+
+        self.keyboard.insert(action, vec![key]);
+        Ok(())
+    }
+
+    // Write the bindings back out, after they've been changed in game.
+    pub fn save(&self, path: &str) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())
+            .and_then(|text| std::fs::write(path, text).map_err(|error| error.to_string()));
+
+        if let Err(error) = result {
+            println!("Couldn't write input config {}: {}", path, error);
+        }
+    }
+}
+
+// The state of every action this frame.
+#[derive(Default, Debug, Resource)]
+pub struct ActionInput {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+
+    // Directions currently held, in the order they were pressed. The last one wins.
+    held_directions: Vec<Action>,
+
+    // When each action was last pressed, until something uses the press up.
+    // Lets a quick tap that happens mid step still count once the step is done.
+    buffered_presses: HashMap<Action, f32>,
+    buffer_window: f32,
+
+    // The step the player is part way through (or last took), see hold_buffer.
+    busy_from: f32,
+    busy_until: f32,
+
+    // When each held action started being held.
+    held_since: HashMap<Action, f32>,
+
+    now: f32
+}
+
+impl ActionInput {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    // The direction being held. If there's more than one, it's whichever was pressed most recently.
+    pub fn direction(&self) -> Option<Action> {
+        self.held_directions.last().copied()
+    }
+
+    // Was this pressed recently, without the press being used up yet?
+    // Presses made during the player's last step only start to go stale once the step is over.
+    pub fn buffered(&self, action: Action) -> bool {
+        self.buffered_presses.get(&action).is_some_and(|pressed_at| {
+            let stale_from = if *pressed_at >= self.busy_from { pressed_at.max(self.busy_until) } else { *pressed_at };
+            self.now - stale_from <= self.buffer_window
+        })
+    }
+
+    // The player is still part way through a step that started this long ago, so anything pressed
+    // since it started has to be kept until it's finished.
+    pub fn hold_buffer(&mut self, busy_for: f32) {
+        self.busy_from = self.now - busy_for;
+        self.busy_until = self.now;
+    }
+
+    // The most recently pressed direction that's still in the buffer.
+    pub fn buffered_direction(&self) -> Option<Action> {
+        Action::ALL.into_iter()
+            .filter(|action| action.is_direction() && self.buffered(*action))
+            .max_by(|a, b| self.buffered_presses[a].total_cmp(&self.buffered_presses[b]))
+    }
+
+    // Use up a buffered press so it doesn't get acted on twice.
+    pub fn consume(&mut self, action: Action) {
+        self.buffered_presses.remove(&action);
+    }
+
+    // How long (in seconds) the action has been held, if it's held.
+    pub fn held_for(&self, action: Action) -> Option<f32> {
+        self.held_since.get(&action).map(|held_since| self.now - held_since)
+    }
+
+    // Update from the set of actions that are held down right now.
+    pub fn update(&mut self, pressed_now: HashSet<Action>, now: f32, buffer_window: f32) {
+        self.now = now;
+        self.buffer_window = buffer_window;
+
+        self.just_pressed = pressed_now.difference(&self.pressed).copied().collect();
+        self.just_released = self.pressed.difference(&pressed_now).copied().collect();
+
+        for action in &self.just_pressed {
+            self.buffered_presses.insert(*action, now);
+            self.held_since.insert(*action, now);
+        }
+        for action in &self.just_released {
+            self.held_since.remove(action);
+        }
+
+        // Forget about released directions, and put newly pressed ones on top.
+        self.held_directions.retain(|action| pressed_now.contains(action));
+        for action in Action::ALL {
+            if action.is_direction() && self.just_pressed.contains(&action) {
+                self.held_directions.push(action);
+            }
+        }
+
+        self.pressed = pressed_now;
+    }
+}
+
+fn load_input_bindings(mut commands: Commands) {
+    commands.insert_resource(InputBindings::load(INPUT_CONFIG_PATH));
+}
+
+// Work out which actions are held from every keyboard and gamepad.
+fn update_action_input(bindings: Res<InputBindings>,
+                       time: Res<Time<Real>>,
+                       keys: Res<ButtonInput<KeyCode>>,
+                       gamepads: Res<Gamepads>,
+                       gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+                       gamepad_axes: Res<Axis<GamepadAxis>>,
+                       mut action_input: ResMut<ActionInput>) {
+    let mut pressed_now = HashSet::new();
+
+    for (action, key_codes) in &bindings.keyboard {
+        if key_codes.iter().any(|key_code| keys.pressed(*key_code)) {
+            pressed_now.insert(*action);
+        }
+    }
+
+    for gamepad in gamepads.iter() {
+        for (action, button_types) in &bindings.gamepad {
+            if button_types.iter().any(|button_type| gamepad_buttons.pressed(GamepadButton::new(gamepad, *button_type))) {
+                pressed_now.insert(*action);
+            }
+        }
+
+        // The left stick works like the d-pad.
+        let stick_x = gamepad_axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.0);
+        let stick_y = gamepad_axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.0);
+        if stick_x > bindings.stick_deadzone { pressed_now.insert(Action::Right); }
+        if stick_x < -bindings.stick_deadzone { pressed_now.insert(Action::Left); }
+        if stick_y > bindings.stick_deadzone { pressed_now.insert(Action::Up); }
+        if stick_y < -bindings.stick_deadzone { pressed_now.insert(Action::Down); }
+    }
+
+    action_input.update(pressed_now, time.elapsed_seconds(), bindings.buffer_window);
+}
+
+pub struct InputPlugin;
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputBindings>();
+        app.init_resource::<ActionInput>();
+
+        app.add_systems(Startup, load_input_bindings);
+
+        // Straight after bevy has read the devices, so everything in Update sees this frame's actions.
+        app.add_systems(PreUpdate, update_action_input.after(InputSystem));
+    }
+}
@@ -0,0 +1,146 @@
+// Let the player interact with things in the world.
+// Face something and press the action button, and whatever is on the tile in front of you
+// gets an InteractEvent. What happens next is up to whoever is listening for it.
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{character::{self, FacingDir, Player, TileMover}, collision::{Blocking, WorldGridCoords, WorldGridCoordsRequired}, dialogue::{InDialogue, OpenDialogueEvent}, input::{Action, ActionInput}, terrain::{TerrainCache, TerrainType}, warp::WarpPending};
+
+// The "A" button.
+const INTERACT_ACTION: Action = Action::A;
+
+// Anything with this component can be interacted with by facing it.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Interactable;
+
+// Sent whenever something is interacted with.
+#[derive(Event)]
+pub struct InteractEvent {
+    pub interactor: Entity, // Who did the interacting.
+    pub target: Entity, // What they interacted with.
+    pub dir: FacingDir // The direction the interactor was facing when they interacted.
+}
+
+// When the action button is pressed, look at the tile in front of the player and interact with whatever is there.
+pub(crate) fn player_interact(mut action_input: ResMut<ActionInput>,
+                              mut interact_event_writer: EventWriter<InteractEvent>,
+                              terrain_cache: Res<TerrainCache>,
+                              player_query: Query<(Entity, &WorldGridCoords, &TileMover), (With<Player>, Without<WarpPending>, Without<InDialogue>)>,
+                              interactable_query: Query<(Entity, &WorldGridCoords), With<Interactable>>) {
+
+    // Pressing the button part way through a step still counts once we've stopped.
+    if !action_input.buffered(INTERACT_ACTION) {
+        return;
+    }
+
+    if let Ok((player_entity, player_grid_coords, tile_mover)) = player_query.get_single() {
+
+        // Can't interact while we're halfway between tiles.
+        if !tile_mover.is_stationary() {
+            return;
+        }
+        action_input.consume(INTERACT_ACTION);
+
+        // The tile we're looking at.
+        let facing_vec = character::facingdir_to_vec(tile_mover.facing_dir);
+        let mut facing_grid_coords = WorldGridCoords {
+            x: player_grid_coords.x + facing_vec.x,
+            y: player_grid_coords.y + facing_vec.y,
+            z: player_grid_coords.z
+        };
+
+        // Talk across counters to whoever is behind them.
+        if terrain_cache.terrain_at(&facing_grid_coords) == TerrainType::Counter {
+            facing_grid_coords.x += facing_vec.x;
+            facing_grid_coords.y += facing_vec.y;
+        }
+
+        // Is there anything there?
+        for (entity, world_grid_coords) in &interactable_query {
+            if *world_grid_coords == facing_grid_coords && entity != player_entity {
+                interact_event_writer.send(InteractEvent {
+                    interactor: player_entity,
+                    target: entity,
+                    dir: tile_mover.facing_dir
+                });
+            }
+        }
+    }
+}
+
+// Anything that can turn should turn to look at whoever is talking to it.
+fn face_interactor(mut interact_event_reader: EventReader<InteractEvent>,
+                   mut tile_mover_query: Query<&mut TileMover, With<Interactable>>) {
+    for event in interact_event_reader.read() {
+        if let Ok(mut tile_mover) = tile_mover_query.get_mut(event.target) {
+
+            // Don't spin around mid step.
+            if tile_mover.is_stationary() {
+                tile_mover.facing_dir = character::opposite_facingdir(event.dir);
+            }
+        }
+    }
+}
+
+// A sign with some text written on it.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Sign {
+    pub text: String
+}
+
+#[derive(Bundle, Default)]
+struct SignBundle {
+    sign: Sign,
+    interactable: Interactable,
+    blocking: Blocking,
+
+    grid_coords: GridCoords,
+    world_grid_coords_required: WorldGridCoordsRequired
+}
+
+impl LdtkEntity for SignBundle {
+    fn bundle_entity(entity_instance: &EntityInstance,
+                     layer_instance: &LayerInstance,
+                     _tileset: Option<&Handle<Image>>,
+                     _tileset_definition: Option<&TilesetDefinition>,
+                     _asset_server: &AssetServer,
+                     _texture_atlases: &mut Assets<TextureAtlasLayout>) -> Self {
+
+        // A sign with nothing written on it is still a sign.
+        let text = entity_instance.get_string_field("Text").cloned().unwrap_or_default();
+
+        SignBundle {
+            sign: Sign { text },
+            grid_coords: GridCoords::from_entity_info(entity_instance, layer_instance),
+            ..Default::default()
+        }
+    }
+}
+
+fn read_sign(mut interact_event_reader: EventReader<InteractEvent>,
+             mut open_dialogue_event_writer: EventWriter<OpenDialogueEvent>,
+             sign_query: Query<&Sign>) {
+    for event in interact_event_reader.read() {
+        if let Ok(sign) = sign_query.get(event.target) {
+            open_dialogue_event_writer.send(OpenDialogueEvent {
+                text: vec![sign.text.clone()],
+                ..default()
+            });
+        }
+    }
+}
+
+pub struct InteractionPlugin;
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<InteractEvent>();
+
+        // Things in the world that only exist to be interacted with.
+        app.register_ldtk_entity::<SignBundle>("Sign");
+
+        // Button presses are per frame, so check for them in Update otherwise we might miss them.
+        app.add_systems(Update, player_interact);
+        app.add_systems(Update, (face_interactor, read_sign).after(player_interact));
+    }
+}
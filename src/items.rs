@@ -0,0 +1,588 @@
+// Items, and the bag the player keeps them in.
+// What each item is lives in assets/all.items.ron, everything else just refers to items by their id.
+// Items can be left lying about in LDtk (Item entities) or tucked away in chests (Chest entities),
+// and are picked up by interacting with them.
+
+use std::collections::HashMap;
+
+use bevy::{asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext}, prelude::*};
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{collision::{Blocking, WorldGridCoordsRequired}, dialogue::OpenDialogueEvent, flags::{FlagValue, GameFlags}, interaction::{Interactable, InteractEvent}, persistence::{self, EntityStates, Persistent}};
+
+const ITEM_DATABASE_PATH: &str = "all.items.ron";
+const ITEM_ICONS_PATH: &str = "items.png";
+const CHEST_PATH: &str = "chest.png";
+
+// How many are left in a chest (or on the ground) when there wasn't room for all of them. Kept in the EntityStates.
+const REMAINING_COUNT: &str = "remaining";
+
+// How many different things fit in the bag.
+pub const INVENTORY_SLOTS: usize = 20;
+
+// What happens when an item is used.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub enum ItemEffect {
+    #[default]
+    None, // Can't be used, just carried around.
+    SetFlag(String, FlagValue), // Set a game flag, e.g. to let the player swim.
+    AddInt(String, i32) // Add to a counter.
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemDefinition {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub icon: usize, // Index of the tile in items.png.
+    #[serde(default = "default_stack_size")]
+    pub stack_size: u32, // How many fit in one slot.
+    #[serde(default)]
+    pub use_effect: ItemEffect,
+    #[serde(default)]
+    pub consumed: bool // Used up when used.
+}
+
+fn default_stack_size() -> u32 {
+    99
+}
+
+// Every item in the game, by id.
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+pub struct ItemDatabase {
+    pub items: HashMap<String, ItemDefinition>
+}
+
+impl ItemDatabase {
+    pub fn get(&self, id: &str) -> Option<&ItemDefinition> {
+        self.items.get(id)
+    }
+}
+
+#[derive(Default)]
+struct ItemDatabaseLoader;
+
+#[derive(Debug, Error)]
+enum ItemDatabaseLoaderError {
+    #[error("Could not read item file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse item file: {0}")]
+    Ron(#[from] ron::error::SpannedError)
+}
+
+impl AssetLoader for ItemDatabaseLoader {
+    type Asset = ItemDatabase;
+    type Settings = ();
+    type Error = ItemDatabaseLoaderError;
+
+    async fn load<'a>(&'a self,
+                      reader: &'a mut Reader<'_>,
+                      _settings: &'a (),
+                      _load_context: &'a mut LoadContext<'_>) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+
+        // The file is just a list, key them by id so they're easy to find.
+        let items = ron::de::from_bytes::<Vec<ItemDefinition>>(&bytes)?;
+        Ok(ItemDatabase {
+            items: items.into_iter().map(|item| (item.id.clone(), item)).collect()
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["items.ron"]
+    }
+}
+
+// Keeps the item database loaded.
+#[derive(Default, Resource)]
+pub struct ItemDatabaseHandle(pub Handle<ItemDatabase>);
+
+fn load_item_database(mut item_database_handle: ResMut<ItemDatabaseHandle>, asset_server: Res<AssetServer>) {
+    item_database_handle.0 = asset_server.load(ITEM_DATABASE_PATH);
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32
+}
+
+#[derive(Clone, Debug, Default, Component, Serialize, Deserialize)]
+pub struct Inventory {
+    pub slots: Vec<ItemStack>
+}
+
+impl Inventory {
+    // How many of an item there are, across every slot.
+    pub fn count(&self, item: &str) -> u32 {
+        self.slots.iter().filter(|stack| stack.item == item).map(|stack| stack.count).sum()
+    }
+
+    // Put as many as will fit in the bag, topping up existing stacks first.
+    // Returns how many were actually added.
+    pub fn add(&mut self, item: &ItemDefinition, count: u32) -> u32 {
+        let mut remaining = count;
+
+        for stack in self.slots.iter_mut().filter(|stack| stack.item == item.id) {
+            let added = remaining.min(item.stack_size.saturating_sub(stack.count));
+            stack.count += added;
+            remaining -= added;
+        }
+
+        while remaining > 0 && self.slots.len() < INVENTORY_SLOTS {
+            let added = remaining.min(item.stack_size.max(1));
+            self.slots.push(ItemStack { item: item.id.clone(), count: added });
+            remaining -= added;
+        }
+
+        count - remaining
+    }
+
+    // Take some out of the bag, emptying the last stacks first. Doesn't take any if there aren't enough.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        if self.count(item) < count {
+            return false;
+        }
+
+        let mut remaining = count;
+        for stack in self.slots.iter_mut().rev().filter(|stack| stack.item == item) {
+            let removed = remaining.min(stack.count);
+            stack.count -= removed;
+            remaining -= removed;
+        }
+        self.slots.retain(|stack| stack.count > 0);
+
+        true
+    }
+}
+
+// Ask for items to be put in (or taken out of) an inventory.
+#[derive(Clone, Event)]
+pub struct GiveItemEvent {
+    pub entity: Entity,
+    pub item: String,
+    pub count: u32
+}
+
+#[derive(Event)]
+pub struct TakeItemEvent {
+    pub entity: Entity,
+    pub item: String,
+    pub count: u32
+}
+
+#[derive(Event)]
+pub struct UseItemEvent {
+    pub entity: Entity,
+    pub item: String
+}
+
+// Sent after an item has been used, or couldn't be.
+#[derive(Event)]
+pub struct ItemUsedEvent {
+    pub entity: Entity,
+    pub item: String,
+    pub used: bool
+}
+
+// Sent whenever an inventory actually changes. Change is how many were added (or taken away, if it's negative).
+#[derive(Event)]
+pub struct InventoryChangedEvent {
+    pub entity: Entity,
+    pub item: String,
+    pub change: i32
+}
+
+// Put items in an inventory, and let everyone know if any actually went in.
+// Returns how many were added, which can be fewer than asked for if the bag is full.
+fn add_items(inventory: &mut Inventory,
+             entity: Entity,
+             item: &ItemDefinition,
+             count: u32,
+             inventory_changed_event_writer: &mut EventWriter<InventoryChangedEvent>) -> u32 {
+    let added = inventory.add(item, count);
+    if added > 0 {
+        inventory_changed_event_writer.send(InventoryChangedEvent {
+            entity,
+            item: item.id.clone(),
+            change: added as i32
+        });
+    }
+    added
+}
+
+fn give_items(mut give_item_event_reader: EventReader<GiveItemEvent>,
+              mut inventory_changed_event_writer: EventWriter<InventoryChangedEvent>,
+              mut inventory_query: Query<&mut Inventory>,
+              mut pending: Local<Vec<GiveItemEvent>>,
+              item_database_handle: Res<ItemDatabaseHandle>,
+              item_databases: Res<Assets<ItemDatabase>>) {
+    // Hang on to anything given before the item database has loaded, rather than letting the events expire.
+    pending.extend(give_item_event_reader.read().cloned());
+    let Some(item_database) = item_databases.get(&item_database_handle.0) else {
+        return;
+    };
+
+    for event in pending.drain(..) {
+        let Some(item) = item_database.get(&event.item) else {
+            println!("There's no such item as {}!", event.item);
+            continue;
+        };
+
+        if let Ok(mut inventory) = inventory_query.get_mut(event.entity) {
+            let added = add_items(&mut inventory, event.entity, item, event.count, &mut inventory_changed_event_writer);
+            if added < event.count {
+                println!("No room for {} more {}.", event.count - added, item.name);
+            }
+        }
+    }
+}
+
+fn take_items(mut take_item_event_reader: EventReader<TakeItemEvent>,
+              mut inventory_changed_event_writer: EventWriter<InventoryChangedEvent>,
+              mut inventory_query: Query<&mut Inventory>) {
+    for event in take_item_event_reader.read() {
+        if let Ok(mut inventory) = inventory_query.get_mut(event.entity) {
+            if inventory.remove(&event.item, event.count) {
+                inventory_changed_event_writer.send(InventoryChangedEvent {
+                    entity: event.entity,
+                    item: event.item.clone(),
+                    change: -(event.count as i32)
+                });
+            }
+        }
+    }
+}
+
+fn use_items(mut use_item_event_reader: EventReader<UseItemEvent>,
+             mut take_item_event_writer: EventWriter<TakeItemEvent>,
+             mut item_used_event_writer: EventWriter<ItemUsedEvent>,
+             mut flags: ResMut<GameFlags>,
+             inventory_query: Query<&Inventory>,
+             item_database_handle: Res<ItemDatabaseHandle>,
+             item_databases: Res<Assets<ItemDatabase>>) {
+    let Some(item_database) = item_databases.get(&item_database_handle.0) else {
+        return;
+    };
+
+    for event in use_item_event_reader.read() {
+        let (Some(item), Ok(inventory)) = (item_database.get(&event.item), inventory_query.get(event.entity)) else {
+            item_used_event_writer.send(ItemUsedEvent { entity: event.entity, item: event.item.clone(), used: false });
+            continue;
+        };
+
+        // Can't use what we haven't got.
+        let used = inventory.count(&item.id) > 0 && match &item.use_effect {
+            ItemEffect::None => false,
+            ItemEffect::SetFlag(name, value) => {
+                flags.set_value(name, value.clone());
+                true
+            },
+            ItemEffect::AddInt(name, amount) => {
+                flags.add_int(name, *amount);
+                true
+            }
+        };
+
+        item_used_event_writer.send(ItemUsedEvent { entity: event.entity, item: item.id.clone(), used });
+        if used && item.consumed {
+            take_item_event_writer.send(TakeItemEvent { entity: event.entity, item: item.id.clone(), count: 1 });
+        }
+    }
+}
+
+// Something that's in the world and can be picked up, or found in a chest.
+#[derive(Clone, Debug, Default, Component)]
+pub struct ItemPickup {
+    pub item: String,
+    pub count: u32
+}
+
+impl ItemPickup {
+    // Items on the ground say what they are with ItemId, chests say what's inside with Contents.
+    fn from_entity_info(entity_instance: &EntityInstance, item_field: &str) -> Self {
+        Self {
+            item: entity_instance.get_string_field(item_field).cloned().unwrap_or_default(),
+            count: entity_instance.get_int_field("Count").copied().unwrap_or(1).max(1) as u32
+        }
+    }
+}
+
+// A chest can be opened once, and stays where it is (open) once it's been emptied.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Chest;
+
+// The chest frames in chest.png.
+const CHEST_CLOSED_FRAME: usize = 0;
+const CHEST_OPEN_FRAME: usize = 1;
+
+// An item lying on the ground.
+#[derive(Bundle, Default)]
+pub struct ItemBundle {
+    sprite_bundle: SpriteBundle,
+    texture_atlas: TextureAtlas,
+    item_pickup: ItemPickup,
+
+    grid_coords: GridCoords,
+    world_grid_coords_required: WorldGridCoordsRequired,
+
+    blocking: Blocking,
+    interactable: Interactable,
+    persistent: Persistent
+}
+
+impl LdtkEntity for ItemBundle {
+    fn bundle_entity(entity_instance: &EntityInstance,
+                     layer_instance: &LayerInstance,
+                     _tileset: Option<&Handle<Image>>,
+                     _tileset_definition: Option<&TilesetDefinition>,
+                     asset_server: &AssetServer,
+                     texture_atlases: &mut Assets<TextureAtlasLayout>) -> Self {
+        // The icon is picked once the item database has loaded.
+        let layout = TextureAtlasLayout::from_grid(UVec2::splat(16), 8, 8, None, None);
+
+        ItemBundle {
+            sprite_bundle: SpriteBundle {
+                texture: asset_server.load(ITEM_ICONS_PATH),
+                ..default()
+            },
+            texture_atlas: TextureAtlas {
+                layout: texture_atlases.add(layout),
+                index: 0
+            },
+            item_pickup: ItemPickup::from_entity_info(entity_instance, "ItemId"),
+            grid_coords: GridCoords::from_entity_info(entity_instance, layer_instance),
+            ..Default::default()
+        }
+    }
+}
+
+// A chest with an item inside.
+#[derive(Bundle, Default)]
+pub struct ChestBundle {
+    sprite_bundle: SpriteBundle,
+    texture_atlas: TextureAtlas,
+    item_pickup: ItemPickup,
+    chest: Chest,
+
+    grid_coords: GridCoords,
+    world_grid_coords_required: WorldGridCoordsRequired,
+
+    blocking: Blocking,
+    interactable: Interactable,
+    persistent: Persistent
+}
+
+impl LdtkEntity for ChestBundle {
+    fn bundle_entity(entity_instance: &EntityInstance,
+                     layer_instance: &LayerInstance,
+                     _tileset: Option<&Handle<Image>>,
+                     _tileset_definition: Option<&TilesetDefinition>,
+                     asset_server: &AssetServer,
+                     texture_atlases: &mut Assets<TextureAtlasLayout>) -> Self {
+        let layout = TextureAtlasLayout::from_grid(UVec2::splat(16), 2, 1, None, None);
+
+        ChestBundle {
+            sprite_bundle: SpriteBundle {
+                texture: asset_server.load(CHEST_PATH),
+                ..default()
+            },
+            texture_atlas: TextureAtlas {
+                layout: texture_atlases.add(layout),
+                index: CHEST_CLOSED_FRAME
+            },
+            item_pickup: ItemPickup::from_entity_info(entity_instance, "Contents"),
+            grid_coords: GridCoords::from_entity_info(entity_instance, layer_instance),
+            ..Default::default()
+        }
+    }
+}
+
+// Show each item on the ground with its own icon.
+fn item_pickup_icons(mut query: Query<(&ItemPickup, &mut TextureAtlas), Without<Chest>>,
+                     item_database_handle: Res<ItemDatabaseHandle>,
+                     item_databases: Res<Assets<ItemDatabase>>) {
+    let Some(item_database) = item_databases.get(&item_database_handle.0) else {
+        return;
+    };
+
+    for (item_pickup, mut texture_atlas) in &mut query {
+        if let Some(item) = item_database.get(&item_pickup.item) {
+            if texture_atlas.index != item.icon {
+                texture_atlas.index = item.icon;
+            }
+        }
+    }
+}
+
+// Chests that have been opened stay open.
+fn chest_frames(entity_states: Res<EntityStates>,
+                mut query: Query<(&EntityIid, &mut TextureAtlas), With<Chest>>) {
+    for (entity_iid, mut texture_atlas) in &mut query {
+        let frame = if entity_states.get_flag(entity_iid, persistence::OPENED) { CHEST_OPEN_FRAME } else { CHEST_CLOSED_FRAME };
+        if texture_atlas.index != frame {
+            texture_atlas.index = frame;
+        }
+    }
+}
+
+// Pick up items and open chests when they're interacted with.
+// The item goes straight in the bag here, so the chest only opens (or the item only disappears) if there was room.
+fn pick_up_items(mut commands: Commands,
+                 mut interact_event_reader: EventReader<InteractEvent>,
+                 mut inventory_changed_event_writer: EventWriter<InventoryChangedEvent>,
+                 mut open_dialogue_event_writer: EventWriter<OpenDialogueEvent>,
+                 mut entity_states: ResMut<EntityStates>,
+                 item_query: Query<(&ItemPickup, &EntityIid, Has<Chest>)>,
+                 mut inventory_query: Query<&mut Inventory>,
+                 item_database_handle: Res<ItemDatabaseHandle>,
+                 item_databases: Res<Assets<ItemDatabase>>) {
+    // Nothing can be picked up until we know what the items are.
+    let Some(item_database) = item_databases.get(&item_database_handle.0) else {
+        interact_event_reader.clear();
+        return;
+    };
+
+    for event in interact_event_reader.read() {
+        let Ok((item_pickup, entity_iid, chest)) = item_query.get(event.target) else {
+            continue;
+        };
+
+        // Only things with somewhere to put it can pick it up.
+        let Ok(mut inventory) = inventory_query.get_mut(event.interactor) else {
+            continue;
+        };
+
+        if chest && entity_states.get_flag(entity_iid, persistence::OPENED) {
+            open_dialogue_event_writer.send(OpenDialogueEvent {
+                text: vec![String::from("The chest is empty.")],
+                ..default()
+            });
+            continue;
+        }
+
+        let Some(item) = item_database.get(&item_pickup.item) else {
+            println!("There's no such item as {}!", item_pickup.item);
+            continue;
+        };
+
+        // Whatever didn't fit last time is still waiting.
+        let count = match entity_states.get_value(entity_iid, REMAINING_COUNT) {
+            Some(FlagValue::Int(remaining)) => (*remaining).max(1) as u32,
+            _ => item_pickup.count
+        };
+
+        let added = add_items(&mut inventory, event.interactor, item, count, &mut inventory_changed_event_writer);
+        if added == 0 {
+            open_dialogue_event_writer.send(OpenDialogueEvent {
+                text: vec![format!("No room for {}.", item.name)],
+                ..default()
+            });
+            continue;
+        }
+
+        // Only empty the chest (or take the item away) once everything's been taken, the rest stays for later.
+        if added < count {
+            entity_states.set_value(entity_iid, REMAINING_COUNT, FlagValue::Int((count - added) as i32));
+        } else if chest {
+            entity_states.set_value(entity_iid, persistence::OPENED, FlagValue::Bool(true));
+        } else {
+            entity_states.remove_permanently(entity_iid);
+            commands.entity(event.target).despawn_recursive();
+        }
+
+        let mut text = vec![if added > 1 { format!("Found {} x{}!", item.name, added) } else { format!("Found {}!", item.name) }];
+        if added < count {
+            text.push(String::from("No room for the rest."));
+        }
+        open_dialogue_event_writer.send(OpenDialogueEvent {
+            text,
+            ..default()
+        });
+    }
+}
+
+pub struct ItemsPlugin;
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ItemDatabase>();
+        app.init_asset_loader::<ItemDatabaseLoader>();
+        app.init_resource::<ItemDatabaseHandle>();
+
+        app.add_event::<GiveItemEvent>();
+        app.add_event::<TakeItemEvent>();
+        app.add_event::<UseItemEvent>();
+        app.add_event::<ItemUsedEvent>();
+        app.add_event::<InventoryChangedEvent>();
+
+        app.add_systems(Startup, load_item_database);
+        app.add_systems(Update, (pick_up_items, use_items, give_items, take_items).chain()
+                                    .after(crate::interaction::player_interact)
+                                    .before(crate::dialogue::open_dialogue));
+        app.add_systems(Update, (item_pickup_icons, chest_frames));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn potion(stack_size: u32) -> ItemDefinition {
+        ItemDefinition {
+            id: String::from("potion"),
+            name: String::from("Potion"),
+            description: String::new(),
+            icon: 0,
+            stack_size,
+            use_effect: ItemEffect::default(),
+            consumed: true
+        }
+    }
+
+    fn stack_counts(inventory: &Inventory) -> Vec<u32> {
+        inventory.slots.iter().map(|stack| stack.count).collect()
+    }
+
+    #[test]
+    fn add_tops_up_stacks_before_starting_new_ones() {
+        let mut inventory = Inventory::default();
+        assert_eq!(inventory.add(&potion(10), 5), 5);
+        assert_eq!(inventory.add(&potion(10), 12), 12);
+        assert_eq!(stack_counts(&inventory), vec![10, 7]);
+        assert_eq!(inventory.count("potion"), 17);
+    }
+
+    #[test]
+    fn add_stops_when_the_bag_is_full() {
+        let mut inventory = Inventory::default();
+        let added = inventory.add(&potion(1), INVENTORY_SLOTS as u32 + 3);
+        assert_eq!(added, INVENTORY_SLOTS as u32);
+        assert_eq!(inventory.slots.len(), INVENTORY_SLOTS);
+        assert_eq!(inventory.add(&potion(1), 1), 0);
+    }
+
+    #[test]
+    fn remove_empties_the_last_stacks_first() {
+        let mut inventory = Inventory::default();
+        inventory.add(&potion(10), 17);
+
+        assert!(inventory.remove("potion", 9));
+        assert_eq!(stack_counts(&inventory), vec![8]);
+
+        assert!(inventory.remove("potion", 8));
+        assert!(inventory.slots.is_empty());
+    }
+
+    #[test]
+    fn remove_takes_nothing_if_there_arent_enough() {
+        let mut inventory = Inventory::default();
+        inventory.add(&potion(10), 3);
+
+        assert!(!inventory.remove("potion", 4));
+        assert!(!inventory.remove("ether", 1));
+        assert_eq!(stack_counts(&inventory), vec![3]);
+    }
+}
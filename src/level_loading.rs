@@ -9,10 +9,10 @@
 
 use std::{collections::{HashMap, HashSet}, thread::current};
 
-use bevy::{app::{FixedUpdate, Plugin}, asset::{Assets, Handle}, log::Level, math::{Rect, Vec2, Vec3Swizzles}, prelude::{run_once, Added, Commands, Component, Entity, Event, EventReader, EventWriter, GlobalTransform, IntoSystemConfigs, Query, Res, ResMut, Resource, With}};
-use bevy_ecs_ldtk::{assets::{LdtkProject, LevelMetadataAccessor}, EntityIid, LevelEvent, LevelIid, LevelSet, Worldly};
+use bevy::{app::{FixedUpdate, Plugin}, log::Level, math::{Rect, Vec2, Vec3Swizzles}, prelude::{run_once, Added, Commands, Component, DetectChanges, Entity, Event, EventReader, EventWriter, GlobalTransform, IntoSystemConfigs, Local, Query, Ref, Res, ResMut, Resource, Visibility, With}};
+use bevy_ecs_ldtk::{assets::LevelMetadataAccessor, EntityIid, LevelEvent, LevelIid, LevelSet, Worldly};
 
-use crate::{character::Player, collision::WorldGridCoords, util::run_if_ldtk_project_resource_available};
+use crate::{character::Player, collision::WorldGridCoords, util::run_if_ldtk_project_resource_available, world_data::LdtkProjects};
 
 // This just tracks what level an entity is currently contained within.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Component)]
@@ -31,7 +31,7 @@ impl Default for CurrentLevel {
 #[derive(Component)]
 pub struct CurrentLevelLoading;
 
-#[derive(Event)]
+#[derive(Clone, Debug, Event)]
 pub enum CurrentLevelChangedEvent {
     Changed(EntityIid, Option<LevelIid>, Option<LevelIid>),
     ChangedAndLoaded(EntityIid, LevelIid)
@@ -43,11 +43,7 @@ fn track_level(mut commands: Commands,
                mut wordly_query: Query<(Entity, &EntityIid, &WorldGridCoords, &GlobalTransform, &mut CurrentLevel)>,
                mut current_level_event_writer: EventWriter<CurrentLevelChangedEvent>,
                level_query: Query<&LevelIid>,
-               ldtk_projects: Query<&Handle<LdtkProject>>,
-               ldtk_project_assets: Res<Assets<LdtkProject>>) {
-    
-    // Get the ldtk project .
-    let ldtk_project = ldtk_project_assets.get(ldtk_projects.single()).expect("ldtk project should be loaded before track_level system runs.");
+               ldtk_projects: LdtkProjects) {
 
     // For each worldy entity that we are keeping track of.
     for (entity, entity_iid, world_grid_coords, global_transform, mut current_level) in &mut wordly_query {
@@ -55,8 +51,10 @@ fn track_level(mut commands: Commands,
         // The level we've chosen that intersects.
         let mut selected_level = None;
 
-        // Go through each level and see which bounds we are contained within.
-        for level in &ldtk_project.json_data().levels {
+        // Go through each level of every project and see which bounds we are contained within.
+        let all_levels = ldtk_projects.iter()
+            .flat_map(|loaded_project| loaded_project.json_data().levels.iter().map(move |level| (loaded_project, level)));
+        for (loaded_project, level) in all_levels {
             let level_bounds = Rect {
                 min: Vec2::new(
                     level.world_x as f32,
@@ -71,8 +69,8 @@ fn track_level(mut commands: Commands,
             // We're within the 2d bounds...
             if level_bounds.contains(global_transform.translation().xy()) {
 
-                // Check if our z coordinate is the same?
-                if world_grid_coords.z == level.world_depth {
+                // Check if our z coordinate is the same? (each project's levels are on their own layers)
+                if world_grid_coords.z == loaded_project.level_depth(level) {
 
                     // We are contained by this level bounds.
                     selected_level = Some(LevelIid::new(level.iid.clone()));
@@ -121,46 +119,56 @@ fn track_level(mut commands: Commands,
     }
 }
 
+// Start loading a level and its neighbours alongside whatever's already loaded.
+// e.g. somewhere the player's about to warp to, so it's ready by the time they get there.
+#[derive(Clone, Debug, Event)]
+pub struct PreloadLevelEvent {
+    pub level_iid: LevelIid
+}
+
 #[derive(Resource, Debug, Default)]
 struct LevelNeighboursCache {
-    neighbours: HashMap<LevelIid, HashSet<LevelIid>>
+    neighbours: HashMap<LevelIid, HashSet<LevelIid>>,
+    // Which world entity (and so which LevelSet) each level belongs to.
+    worlds: HashMap<LevelIid, Entity>
 }
 
 fn cache_level_neighbours(mut cache: ResMut<LevelNeighboursCache>,
-                          ldtk_projects: Query<&Handle<LdtkProject>>,
-                          ldtk_project_assets: Res<Assets<LdtkProject>>) {
-    
-    // Get the ldtk project .
-    let ldtk_project = ldtk_project_assets.get(ldtk_projects.single()).expect("ldtk project should be loaded before track_level system runs.");
+                          ldtk_projects: LdtkProjects) {
     
     // Clear the cache.
     cache.neighbours.clear();
+    cache.worlds.clear();
 
     // Loop through all the levels and re-calculate their neighbours.
-    for level in &ldtk_project.json_data().levels {
-        let mut levelset = HashSet::new();
-        for neighbour in &level.neighbours {
-
-            // Take the neighbour list here, and filter out any levels that don't share a world_depth.
-            for neighbour_level in &ldtk_project.json_data().levels {
-                if neighbour_level.iid == neighbour.level_iid {
-                    if neighbour_level.world_depth == level.world_depth {
-
-                        // This one matches!
-                        levelset.insert(LevelIid::new(&neighbour_level.iid));
+    // Neighbours are only ever in the same project.
+    for loaded_project in ldtk_projects.iter() {
+        for level in &loaded_project.json_data().levels {
+            let mut levelset = HashSet::new();
+            for neighbour in &level.neighbours {
+
+                // Take the neighbour list here, and filter out any levels that don't share a world_depth.
+                for neighbour_level in &loaded_project.json_data().levels {
+                    if neighbour_level.iid == neighbour.level_iid {
+                        if neighbour_level.world_depth == level.world_depth {
+
+                            // This one matches!
+                            levelset.insert(LevelIid::new(&neighbour_level.iid));
+                        }
                     }
                 }
             }
-        }
 
-        cache.neighbours.insert(LevelIid::new(level.iid.clone()), levelset);
+            cache.neighbours.insert(LevelIid::new(level.iid.clone()), levelset);
+            cache.worlds.insert(LevelIid::new(level.iid.clone()), loaded_project.entity);
+        }
     }
 }
 
 fn load_levels(neighbours_cache: Res<LevelNeighboursCache>,
                mut current_level_changed_reader: EventReader<CurrentLevelChangedEvent>,
                player_query: Query<&EntityIid, (With<Player>, With<CurrentLevel>)>,
-               mut level_set_query: Query<&mut LevelSet>) {
+               mut level_set_query: Query<(Entity, &mut LevelSet)>) {
 
     // Is the player about?
     if let Ok(player_iid) = player_query.get_single() {
@@ -175,20 +183,23 @@ fn load_levels(neighbours_cache: Res<LevelNeighboursCache>,
                     // Get the neighbouring levels (from our handy cache that excludes neighbours not on the same world_depth)
                     if let Some(neighbours) = neighbours_cache.neighbours.get(new_level_iid) {
 
-                        // Grab the the level set and update it.
-                        if let Ok(mut level_set) = level_set_query.get_single_mut() {
-
-                            // All of the neighbours
-                            let mut levels_to_be_loaded = HashSet::new();
-                            for neighbour in neighbours {
-                                levels_to_be_loaded.insert(neighbour.clone());
-                            }
+                        // All of the neighbours
+                        let mut levels_to_be_loaded = HashSet::new();
+                        for neighbour in neighbours {
+                            levels_to_be_loaded.insert(neighbour.clone());
+                        }
 
-                            // And don't forget the level that we are currently on, otherwise we'd unload that =/
-                            levels_to_be_loaded.insert(new_level_iid.clone());
+                        // And don't forget the level that we are currently on, otherwise we'd unload that =/
+                        levels_to_be_loaded.insert(new_level_iid.clone());
 
-                            // Update the level set component.
-                            level_set.iids = levels_to_be_loaded;
+                        // They all go in the level set of the world the level is in, every other world gets emptied.
+                        let new_world = neighbours_cache.worlds.get(new_level_iid);
+                        for (world_entity, mut level_set) in &mut level_set_query {
+                            if Some(&world_entity) == new_world {
+                                level_set.iids = levels_to_be_loaded.clone();
+                            } else if !level_set.iids.is_empty() {
+                                level_set.iids.clear();
+                            }
                         }
 
                     }
@@ -199,6 +210,56 @@ fn load_levels(neighbours_cache: Res<LevelNeighboursCache>,
     }
 }
 
+fn preload_levels(neighbours_cache: Res<LevelNeighboursCache>,
+                  mut preload_level_event_reader: EventReader<PreloadLevelEvent>,
+                  mut level_set_query: Query<&mut LevelSet>) {
+    for preload_level_event in preload_level_event_reader.read() {
+        let level_iid = &preload_level_event.level_iid;
+
+        // Into the level set of the world it's in, on top of the levels that are already there.
+        // They get unloaded as normal once the player's somewhere else.
+        let Some(world_entity) = neighbours_cache.worlds.get(level_iid) else {
+            continue;
+        };
+        if let Ok(mut level_set) = level_set_query.get_mut(*world_entity) {
+            level_set.iids.insert(level_iid.clone());
+            for neighbour in neighbours_cache.neighbours.get(level_iid).into_iter().flatten() {
+                level_set.iids.insert(neighbour.clone());
+            }
+        }
+    }
+}
+
+// Preloaded levels can be on a different layer to the player (the inside of the house they're about to go in)
+// and overlap the levels they're actually in, so only the levels on the player's layer get shown.
+// Only needs doing when the player changes layer, or when new levels turn up.
+fn show_player_layer(player_query: Query<&WorldGridCoords, With<Player>>,
+                     mut level_query: Query<(Ref<LevelIid>, &mut Visibility)>,
+                     mut shown_layer: Local<Option<i32>>,
+                     ldtk_projects: LdtkProjects) {
+    let Ok(player_grid_coords) = player_query.get_single() else {
+        return;
+    };
+
+    let layer_changed = *shown_layer != Some(player_grid_coords.z);
+    *shown_layer = Some(player_grid_coords.z);
+
+    for (level_iid, mut visibility) in &mut level_query {
+        if !layer_changed && !level_iid.is_added() {
+            continue;
+        }
+
+        let Ok((loaded_project, level)) = ldtk_projects.level(level_iid.get()) else {
+            continue;
+        };
+
+        let layer_visibility = if loaded_project.level_depth(level) == player_grid_coords.z { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != layer_visibility {
+            *visibility = layer_visibility;
+        }
+    }
+}
+
 fn check_levels_loaded(mut commands: Commands,
                        current_level_query: Query<(Entity, &EntityIid, &CurrentLevel), With<CurrentLevelLoading>>,
                        level_query: Query<&LevelIid, Added<LevelIid>>,
@@ -228,6 +289,7 @@ impl Plugin for LevelLoadingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         // Events
         app.add_event::<CurrentLevelChangedEvent>();
+        app.add_event::<PreloadLevelEvent>();
 
         // Resources.
         app.init_resource::<LevelNeighboursCache>();
@@ -237,6 +299,8 @@ impl Plugin for LevelLoadingPlugin {
 
         // Level tracking and level loading.
         app.add_systems(FixedUpdate, track_level.run_if(run_if_ldtk_project_resource_available));
-        app.add_systems(FixedUpdate, (load_levels, check_levels_loaded));
+        // Preloading goes after, so the level set changing for the player moving doesn't throw away what's being preloaded.
+        app.add_systems(FixedUpdate, ((load_levels, preload_levels).chain(), check_levels_loaded));
+        app.add_systems(FixedUpdate, show_player_layer.run_if(run_if_ldtk_project_resource_available));
     }
 }
\ No newline at end of file
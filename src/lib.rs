@@ -0,0 +1,25 @@
+// The game itself, split out from main.rs so that benchmarks, tools and tests can use it too.
+
+pub mod audio;
+pub mod warp;
+pub mod palette;
+pub mod collision;
+pub mod camera;
+pub mod character;
+pub mod interaction;
+pub mod dialogue;
+pub mod font;
+pub mod dialogue_graph;
+pub mod flags;
+pub mod input;
+pub mod npc;
+pub mod terrain;
+pub mod level_loading;
+pub mod util;
+pub mod post_process;
+pub mod save;
+pub mod persistence;
+pub mod items;
+pub mod menu;
+pub mod transition;
+pub mod world_data;
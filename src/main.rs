@@ -6,17 +6,7 @@ use bevy::time;
 use bevy_ecs_tilemap::prelude::*;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use palette::PalettePlugin;
-
-mod audio;
-mod warp;
-mod palette;
-mod collision;
-mod camera;
-mod character;
-mod level_loading;
-mod util;
-mod post_process;
+use bevy_plat::{audio, camera, character, collision, dialogue, dialogue_graph, flags, font, input, interaction, items, level_loading, menu, npc, palette::PalettePlugin, persistence, post_process, save, terrain, transition, warp, world_data};
 
 const FIXED_TIMESTEP: f64 = 1.0 / 60.0;
 
@@ -34,13 +24,19 @@ fn startup(
 
     commands.spawn(camera::PlayerFollowCameraBundle::default());
 
-    // First level to load.
-    commands.spawn(LdtkWorldBundle {
-        ldtk_handle: asset_server.load("world.ldtk"),
-        level_set: LevelSet::from_iids(["df73df90-9b00-11ef-b670-2db67582fbfe"]),
-        //level_set: LevelSet::from_iids(["79d1ad10-3b70-11ee-be03-9d15b076a939"]),
-        ..Default::default()
-    });
+    // A world for each project, each with its own set of levels.
+    // No levels to begin with, the save module picks where to start once the projects have loaded.
+    // (either where the Player was placed in LDtk, or wherever a save file says)
+    for (index, path) in world_data::WORLD_PROJECTS.iter().enumerate() {
+        commands.spawn((
+            LdtkWorldBundle {
+                ldtk_handle: asset_server.load(*path),
+                level_set: LevelSet::default(),
+                ..Default::default()
+            },
+            world_data::WorldProject::new(index, path)
+        ));
+    }
 
     // let egg_char_anim_handle = asset_server.load("egg_stomp-Sheet.png");
     // let egg_char_anim_atlas = TextureAtlas::from_grid(egg_char_anim_handle, Vec2::new(16.0, 16.0), 16, 1, None, None);
@@ -85,12 +81,26 @@ fn main() {
         })
         //.insert_resource(LevelSelection::Indices(LevelIndices { level: 0, world: None }))
 
+        .add_plugins(world_data::WorldDataPlugin)
+        .add_plugins(input::InputPlugin)
         .add_plugins(audio::AudioPlugin)
         .add_plugins(level_loading::LevelLoadingPlugin)
         .add_plugins(collision::CollisionPlugin)
+        .add_plugins(terrain::TerrainPlugin)
         .add_plugins(camera::PlayerFollowCameraPlugin)
         .add_plugins(character::CharacterPlugin)
+        .add_plugins(interaction::InteractionPlugin)
+        .add_plugins(font::FontPlugin)
+        .add_plugins(dialogue::DialoguePlugin)
+        .add_plugins(dialogue_graph::DialogueGraphPlugin)
+        .add_plugins(flags::FlagsPlugin)
+        .add_plugins(npc::NpcPlugin)
+        .add_plugins(transition::TransitionPlugin)
         .add_plugins(warp::WarpPlugin)
+        .add_plugins(persistence::PersistencePlugin)
+        .add_plugins(items::ItemsPlugin)
+        .add_plugins(save::SavePlugin)
+        .add_plugins(menu::MenuPlugin)
         .add_plugins(PalettePlugin)
 
         .insert_resource(Time::<Fixed>::from_seconds(FIXED_TIMESTEP))
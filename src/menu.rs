@@ -0,0 +1,525 @@
+// The start menu. Press Start to pause the game and look through your items, save, or change some options.
+// Everything is drawn in the same 160x144 pixel space as the world, using only the four grey bands
+// the palette shader knows about, so the menu gets recoloured along with everything else.
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{audio::MusicVolume, character::Player, dialogue::InDialogue, font::{PixelText, PixelTextBundle}, input::{Action, ActionInput, InputBindings, INPUT_CONFIG_PATH}, items::{Inventory, ItemDatabase, ItemDatabaseHandle, ItemUsedEvent, UseItemEvent}, palette::{PaletteChoice, PALETTE_PRESETS}, post_process::palette_band_colour, save::{self, SaveGameEvent, SaveResultEvent, SaveSlotInfo}, warp::WarpPending};
+
+const MENU_ACTION: Action = Action::Start;
+const SELECT_ACTION: Action = Action::A;
+const BACK_ACTION: Action = Action::B;
+
+// How many save slots there are to pick from.
+const SAVE_SLOTS: usize = 3;
+
+// Music volume goes up and down in steps of this much.
+const VOLUME_STEPS: f32 = 10.0;
+
+// The view is 160x144, with the camera in the middle.
+const VIEW_SIZE: Vec2 = Vec2::new(160.0, 144.0);
+
+// Over the top of the world, and any dialogue boxes.
+const MENU_Z: f32 = 110.0;
+
+const BOX_BORDER: f32 = 2.0;
+const BOX_PADDING: Vec2 = Vec2::new(8.0, 8.0);
+const LINE_HEIGHT: f32 = 10.0;
+
+// The main menu sits in the top right, like it always has.
+const MAIN_WIDTH: f32 = 72.0;
+
+// Item descriptions and messages go in a box along the bottom.
+const FOOTER_HEIGHT: f32 = 40.0;
+
+// How many items fit on the items page at once, above the description. The rest scroll.
+const ITEM_ROWS: usize = ((VIEW_SIZE.y - FOOTER_HEIGHT - BOX_PADDING.y * 2.0) / LINE_HEIGHT) as usize;
+
+const MAIN_ENTRIES: [&str; 4] = ["ITEMS", "SAVE", "OPTIONS", "QUIT"];
+const OPTION_ENTRIES: usize = 3; // Music, palette and keys.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum MenuPage {
+    #[default]
+    Main,
+    Items,
+    Save,
+    Options,
+    Keys
+}
+
+// Everything about the menu that isn't drawing it.
+#[derive(Default, Resource)]
+pub struct StartMenu {
+    open: bool,
+    page: MenuPage,
+    main_cursor: usize, // Kept separately so going back to the main menu puts the cursor back where it was.
+    cursor: usize,
+    scroll: usize, // The first row shown, on pages that don't fit in their box.
+    message: Option<String>, // Shown in the footer until the cursor moves.
+    rebinding: Option<Action>, // Waiting for a key to bind to this action.
+    slot_infos: Option<Vec<Option<SaveSlotInfo>>> // What's in each save slot, read when the save page is shown.
+}
+
+impl StartMenu {
+    fn go_to(&mut self, page: MenuPage) {
+        self.page = page;
+        self.cursor = 0;
+        self.scroll = 0;
+        self.message = None;
+        self.slot_infos = None;
+    }
+}
+
+// Which part of the menu a box is for.
+#[derive(Clone, Copy, PartialEq)]
+enum MenuPanel {
+    Main, // The list of pages, always shown.
+    Page, // Whichever page is open, covering the whole view.
+    Footer // Descriptions and messages.
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+// The border of a box, which everything else in the box hangs off.
+#[derive(Component)]
+struct MenuPanelBorder(MenuPanel);
+
+#[derive(Component)]
+struct MenuPanelInside;
+
+#[derive(Component)]
+struct MenuPanelText;
+
+fn spawn_panel(parent: &mut ChildBuilder, panel: MenuPanel, z: f32) {
+    parent.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: palette_band_colour(3),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, z),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        MenuPanelBorder(panel)
+    )).with_children(|parent| {
+        parent.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: palette_band_colour(0),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                ..default()
+            },
+            MenuPanelInside
+        ));
+
+        parent.spawn((
+            PixelTextBundle {
+                text: PixelText::new(""),
+                spatial: SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, 0.2))
+            },
+            MenuPanelText
+        ));
+    });
+}
+
+// Stop the world and open the menu, or close it again.
+fn toggle_menu(mut commands: Commands,
+               mut action_input: ResMut<ActionInput>,
+               mut start_menu: ResMut<StartMenu>,
+               mut time: ResMut<Time<Virtual>>,
+               camera_query: Query<Entity, With<Camera2d>>,
+               menu_root_query: Query<Entity, With<MenuRoot>>,
+               player_query: Query<(), (With<Player>, Without<InDialogue>, Without<WarpPending>)>) {
+    let close = start_menu.open && (action_input.just_pressed(MENU_ACTION) ||
+                                    (action_input.just_pressed(BACK_ACTION) && start_menu.page == MenuPage::Main && start_menu.rebinding.is_none()));
+
+    if close {
+        start_menu.open = false;
+        for entity in &menu_root_query {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        // Don't let anything pressed in the menu carry over into the world.
+        for action in Action::ALL {
+            action_input.consume(action);
+        }
+        time.unpause();
+        return;
+    }
+
+    // Can only open the menu when the player is free to move about.
+    if start_menu.open || !action_input.just_pressed(MENU_ACTION) || player_query.is_empty() {
+        return;
+    }
+    let Ok(camera_entity) = camera_query.get_single() else {
+        return;
+    };
+
+    action_input.consume(MENU_ACTION);
+    *start_menu = StartMenu {
+        open: true,
+        main_cursor: start_menu.main_cursor,
+        ..default()
+    };
+
+    // Pausing virtual time stops FixedUpdate, which is where the world does all its moving.
+    time.pause();
+
+    // The menu hangs off the camera so it stays put on screen.
+    commands.entity(camera_entity).with_children(|parent| {
+        parent.spawn((SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, MENU_Z)), MenuRoot))
+            .with_children(|parent| {
+                spawn_panel(parent, MenuPanel::Main, 0.0);
+                spawn_panel(parent, MenuPanel::Page, 1.0);
+                spawn_panel(parent, MenuPanel::Footer, 2.0);
+            });
+    });
+}
+
+fn move_cursor(cursor: &mut usize, count: usize, action_input: &ActionInput) -> bool {
+    if count == 0 {
+        return false;
+    }
+
+    let old_cursor = *cursor;
+    if action_input.just_pressed(Action::Up) {
+        *cursor = (*cursor + count - 1) % count;
+    }
+    if action_input.just_pressed(Action::Down) {
+        *cursor = (*cursor + 1) % count;
+    }
+    *cursor != old_cursor
+}
+
+// Left and right, for changing options.
+fn left_right(action_input: &ActionInput) -> i32 {
+    action_input.just_pressed(Action::Right) as i32 - action_input.just_pressed(Action::Left) as i32
+}
+
+// Say how saving went, and look at the slots again so the new save shows up.
+fn show_save_result(mut start_menu: ResMut<StartMenu>,
+                    mut save_result_event_reader: EventReader<SaveResultEvent>) {
+    for event in save_result_event_reader.read() {
+        if start_menu.open && start_menu.page == MenuPage::Save {
+            start_menu.message = Some(if event.saved { format!("Saved to slot {}.", event.slot + 1) } else { String::from("Couldn't save!") });
+            start_menu.slot_infos = None;
+        }
+    }
+}
+
+// Say whether the item that was picked in the menu did anything.
+fn show_item_used(mut start_menu: ResMut<StartMenu>,
+                  mut item_used_event_reader: EventReader<ItemUsedEvent>,
+                  player_query: Query<(), With<Player>>,
+                  item_database_handle: Res<ItemDatabaseHandle>,
+                  item_databases: Res<Assets<ItemDatabase>>) {
+    for event in item_used_event_reader.read() {
+        if !start_menu.open || start_menu.page != MenuPage::Items || !player_query.contains(event.entity) {
+            continue;
+        }
+
+        let name = item_databases.get(&item_database_handle.0)
+            .and_then(|item_database| item_database.get(&event.item))
+            .map_or(event.item.clone(), |item| item.name.clone());
+        start_menu.message = Some(if event.used { format!("Used the {}.", name) } else { format!("The {} can't be used.", name) });
+    }
+}
+
+fn navigate_menu(mut action_input: ResMut<ActionInput>,
+                 mut start_menu: ResMut<StartMenu>,
+                 mut input_bindings: ResMut<InputBindings>,
+                 mut music_volume: ResMut<MusicVolume>,
+                 mut palette_choice: ResMut<PaletteChoice>,
+                 mut save_game_event_writer: EventWriter<SaveGameEvent>,
+                 mut use_item_event_writer: EventWriter<UseItemEvent>,
+                 mut app_exit_event_writer: EventWriter<AppExit>,
+                 keys: Res<ButtonInput<KeyCode>>,
+                 player_query: Query<(Entity, &Inventory), With<Player>>) {
+    if !start_menu.open {
+        return;
+    }
+
+    // Waiting for a new key, which takes priority over everything else. Escape gives up.
+    if let Some(action) = start_menu.rebinding {
+        if let Some(key) = keys.get_just_pressed().next() {
+            start_menu.rebinding = None;
+            start_menu.message = None;
+            if *key != KeyCode::Escape {
+                match input_bindings.rebind_key(action, *key) {
+                    Ok(()) => input_bindings.save(INPUT_CONFIG_PATH),
+                    Err(other) => start_menu.message = Some(format!("{:?} is already used for {:?}.", key, other))
+                }
+            }
+        }
+    } else {
+        let back = action_input.just_pressed(BACK_ACTION);
+        let select = action_input.just_pressed(SELECT_ACTION);
+
+        match start_menu.page {
+            MenuPage::Main => {
+                let mut main_cursor = start_menu.main_cursor;
+                move_cursor(&mut main_cursor, MAIN_ENTRIES.len(), &action_input);
+                start_menu.main_cursor = main_cursor;
+
+                if select {
+                    match main_cursor {
+                        0 => start_menu.go_to(MenuPage::Items),
+                        1 => start_menu.go_to(MenuPage::Save),
+                        2 => start_menu.go_to(MenuPage::Options),
+                        _ => { app_exit_event_writer.send(AppExit::Success); }
+                    }
+                }
+            },
+            MenuPage::Items => {
+                let Ok((player_entity, inventory)) = player_query.get_single() else {
+                    start_menu.go_to(MenuPage::Main);
+                    return;
+                };
+
+                let mut cursor = start_menu.cursor.min(inventory.slots.len().saturating_sub(1));
+                if move_cursor(&mut cursor, inventory.slots.len(), &action_input) {
+                    start_menu.message = None;
+                }
+                start_menu.cursor = cursor;
+                start_menu.scroll = scroll_to_cursor(start_menu.scroll, cursor, ITEM_ROWS);
+
+                // What happened is shown once the item has actually been used. (see show_item_used)
+                if select {
+                    if let Some(stack) = inventory.slots.get(cursor) {
+                        use_item_event_writer.send(UseItemEvent { entity: player_entity, item: stack.item.clone() });
+                    }
+                }
+
+                if back {
+                    start_menu.go_to(MenuPage::Main);
+                }
+            },
+            MenuPage::Save => {
+                let mut cursor = start_menu.cursor;
+                if move_cursor(&mut cursor, SAVE_SLOTS, &action_input) {
+                    start_menu.message = None;
+                }
+                start_menu.cursor = cursor;
+
+                // The message and slot list are updated once the save has been written. (see show_save_result)
+                if select {
+                    save_game_event_writer.send(SaveGameEvent { slot: cursor });
+                }
+
+                if back {
+                    start_menu.go_to(MenuPage::Main);
+                }
+            },
+            MenuPage::Options => {
+                let mut cursor = start_menu.cursor;
+                move_cursor(&mut cursor, OPTION_ENTRIES, &action_input);
+                start_menu.cursor = cursor;
+
+                let change = left_right(&action_input);
+                match cursor {
+                    0 if change != 0 => {
+                        music_volume.volume = (music_volume.volume + change as f32 / VOLUME_STEPS).clamp(0.0, 1.0);
+                    },
+                    1 if change != 0 => {
+                        // Cycle through "the level's own palette" and then each of the presets.
+                        let choices = PALETTE_PRESETS.len() as i32 + 1;
+                        let current = palette_choice.preset.map_or(0, |preset| preset as i32 + 1);
+                        let next = (current + change + choices) % choices;
+                        palette_choice.preset = if next == 0 { None } else { Some(next as usize - 1) };
+                    },
+                    2 if select => start_menu.go_to(MenuPage::Keys),
+                    _ => {}
+                }
+
+                if back {
+                    start_menu.go_to(MenuPage::Main);
+                    start_menu.main_cursor = 2;
+                }
+            },
+            MenuPage::Keys => {
+                let mut cursor = start_menu.cursor;
+                move_cursor(&mut cursor, Action::ALL.len(), &action_input);
+                start_menu.cursor = cursor;
+
+                if select {
+                    start_menu.rebinding = Some(Action::ALL[cursor]);
+                    start_menu.message = Some(format!("Press a key for {:?}.\n(Esc to cancel)", Action::ALL[cursor]));
+                }
+
+                if back {
+                    start_menu.go_to(MenuPage::Options);
+                    start_menu.cursor = 2;
+                }
+            }
+        }
+    }
+
+    // Nothing pressed in the menu should do anything else.
+    for action in Action::ALL {
+        action_input.consume(action);
+    }
+}
+
+// A list of lines with a cursor next to the selected one.
+// Move a scrolling list just far enough to keep the cursor in view.
+fn scroll_to_cursor(scroll: usize, cursor: usize, rows: usize) -> usize {
+    if cursor < scroll {
+        cursor
+    } else if cursor >= scroll + rows {
+        cursor + 1 - rows
+    } else {
+        scroll
+    }
+}
+
+fn cursor_lines(lines: &[String], cursor: usize) -> String {
+    lines.iter().enumerate()
+        .map(|(index, line)| format!("{}{}", if index == cursor { "> " } else { "  " }, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_play_time(seconds: f64) -> String {
+    let minutes = (seconds / 60.0) as u64;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+fn key_name(key: &KeyCode) -> String {
+    let name = format!("{:?}", key);
+    name.strip_prefix("Key").or_else(|| name.strip_prefix("Arrow")).unwrap_or(&name).to_string()
+}
+
+// Work out what each box should show, and where.
+fn draw_menu(mut start_menu: ResMut<StartMenu>,
+             input_bindings: Res<InputBindings>,
+             music_volume: Res<MusicVolume>,
+             palette_choice: Res<PaletteChoice>,
+             player_query: Query<&Inventory, With<Player>>,
+             item_database_handle: Res<ItemDatabaseHandle>,
+             item_databases: Res<Assets<ItemDatabase>>,
+             mut border_query: Query<(&MenuPanelBorder, &mut Sprite, &mut Transform, &mut Visibility, &Children)>,
+             mut inside_query: Query<&mut Sprite, (With<MenuPanelInside>, Without<MenuPanelBorder>)>,
+             mut text_query: Query<(&mut PixelText, &mut Transform), (With<MenuPanelText>, Without<MenuPanelBorder>)>) {
+    if !start_menu.open {
+        return;
+    }
+
+    let item_database = item_databases.get(&item_database_handle.0);
+    let inventory = player_query.get_single().ok();
+
+    // Look at the save slots when we first get to the save page (and again after saving).
+    if start_menu.page == MenuPage::Save && start_menu.slot_infos.is_none() {
+        start_menu.slot_infos = Some((0..SAVE_SLOTS).map(save::read_slot_info).collect());
+    }
+
+    let main_text = cursor_lines(&MAIN_ENTRIES.map(String::from), start_menu.main_cursor);
+
+    let page_text = match start_menu.page {
+        MenuPage::Main => None,
+        MenuPage::Items => Some(match inventory {
+            Some(inventory) if !inventory.slots.is_empty() => {
+                // Only the rows that fit, scrolled to wherever the cursor is.
+                let lines: Vec<String> = inventory.slots.iter().skip(start_menu.scroll).take(ITEM_ROWS).map(|stack| {
+                    let name = item_database.and_then(|item_database| item_database.get(&stack.item))
+                        .map_or(stack.item.clone(), |item| item.name.clone());
+                    format!("{:<12}x{}", name, stack.count)
+                }).collect();
+                cursor_lines(&lines, start_menu.cursor.wrapping_sub(start_menu.scroll))
+            },
+            _ => String::from("Nothing here.")
+        }),
+        MenuPage::Save => {
+            let lines: Vec<String> = start_menu.slot_infos.iter().flatten().enumerate().map(|(slot, slot_info)| match slot_info {
+                Some(slot_info) => format!("{} {}\n   {}", slot + 1, slot_info.location_name, format_play_time(slot_info.play_time)),
+                None => format!("{} ----\n", slot + 1)
+            }).collect();
+            Some(cursor_lines(&lines, start_menu.cursor))
+        },
+        MenuPage::Options => {
+            let palette_name = palette_choice.preset.map_or("LEVEL", |preset| PALETTE_PRESETS[preset].0);
+            let lines = vec![
+                format!("MUSIC   < {} >", (music_volume.volume * VOLUME_STEPS).round() as i32),
+                format!("PALETTE < {} >", palette_name),
+                String::from("KEYS")
+            ];
+            Some(cursor_lines(&lines, start_menu.cursor))
+        },
+        MenuPage::Keys => {
+            let lines: Vec<String> = Action::ALL.iter().map(|action| {
+                let key = input_bindings.keyboard.get(action).and_then(|keys| keys.first()).map_or(String::from("-"), key_name);
+                format!("{:<7}{}", format!("{:?}", action), key)
+            }).collect();
+            Some(cursor_lines(&lines, start_menu.cursor))
+        }
+    };
+
+    // Item descriptions, unless there's something more important to say.
+    let footer_text = start_menu.message.clone().or_else(|| {
+        if start_menu.page != MenuPage::Items {
+            return None;
+        }
+        let stack = inventory?.slots.get(start_menu.cursor)?;
+        item_database?.get(&stack.item).map(|item| item.description.clone())
+    });
+
+    // Where each box goes. (centre, size)
+    let main_size = Vec2::new(MAIN_WIDTH, MAIN_ENTRIES.len() as f32 * LINE_HEIGHT + BOX_PADDING.y * 2.0);
+    let page_size = if footer_text.is_some() { Vec2::new(VIEW_SIZE.x, VIEW_SIZE.y - FOOTER_HEIGHT) } else { VIEW_SIZE };
+    let footer_size = Vec2::new(VIEW_SIZE.x, FOOTER_HEIGHT);
+
+    let layout = |panel: MenuPanel| -> Option<(Vec2, Vec2, String)> {
+        match panel {
+            MenuPanel::Main => Some((Vec2::new((VIEW_SIZE.x - main_size.x) / 2.0, (VIEW_SIZE.y - main_size.y) / 2.0), main_size, main_text.clone())),
+            MenuPanel::Page => page_text.clone().map(|text| (Vec2::new(0.0, (VIEW_SIZE.y - page_size.y) / 2.0), page_size, text)),
+            MenuPanel::Footer => footer_text.clone().map(|text| (Vec2::new(0.0, (footer_size.y - VIEW_SIZE.y) / 2.0), footer_size, text))
+        }
+    };
+
+    for (border, mut sprite, mut transform, mut visibility, children) in &mut border_query {
+        let Some((centre, size, text)) = layout(border.0) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        *visibility = Visibility::Inherited;
+        sprite.custom_size = Some(size);
+        transform.translation.x = centre.x;
+        transform.translation.y = centre.y;
+
+        for child in children.iter() {
+            if let Ok(mut inside_sprite) = inside_query.get_mut(*child) {
+                inside_sprite.custom_size = Some(size - Vec2::splat(BOX_BORDER * 2.0));
+            }
+
+            // Text starts from the top left corner.
+            if let Ok((mut panel_text, mut text_transform)) = text_query.get_mut(*child) {
+                text_transform.translation.x = BOX_PADDING.x - size.x / 2.0;
+                text_transform.translation.y = size.y / 2.0 - BOX_PADDING.y;
+                // Long descriptions wrap inside the box.
+                let max_width = Some((size.x - BOX_PADDING.x * 2.0) as u32);
+                if panel_text.text() != text || panel_text.max_width != max_width {
+                    panel_text.set_text(text.clone());
+                    panel_text.max_width = max_width;
+                }
+            }
+        }
+    }
+}
+
+pub struct MenuPlugin;
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StartMenu>();
+
+        // Before anything else gets a chance to act on the button presses meant for the menu.
+        app.add_systems(Update, (toggle_menu, show_save_result, show_item_used, navigate_menu, draw_menu)
+                                    .chain()
+                                    .before(crate::interaction::player_interact)
+                                    .after(crate::save::save_game));
+    }
+}
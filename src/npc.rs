@@ -0,0 +1,232 @@
+// Make NPCs do something other than stand around.
+// Behaviours only ever decide which way an NPC wants to move, the actual moving (and bumping into things)
+// is left to the TileMover just like it is for the player.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, utils::ldtk_grid_coords_to_grid_coords};
+use rand::Rng;
+
+use crate::{character::{self, FacingDir, HiddenByFlags, MoveDir, Player, TileMover}, collision::{BlockedTilesCache, TileOccupancy, WorldGridCoords}, dialogue::InDialogue, flags::GameFlags, terrain::TerrainCache};
+
+// How long a wandering NPC waits between steps (picked randomly between these).
+const WANDER_IDLE_MIN: Duration = Duration::from_millis(800);
+const WANDER_IDLE_MAX: Duration = Duration::from_millis(3000);
+
+// How long a patrolling NPC waits when it reaches a point on its path.
+const PATROL_IDLE: Duration = Duration::from_millis(1000);
+
+// How close the player needs to be before a LookAtPlayer NPC notices them.
+const DEFAULT_LOOK_RANGE: i32 = 4;
+
+#[derive(Clone, Debug, Default)]
+pub enum NpcBehaviourMode {
+    #[default]
+    Stationary, // Never moves.
+    Wander { radius: i32 }, // Randomly walks about, never further than radius tiles from where it started.
+    Patrol, // Walks between the points of the patrol path, in order, looping back to the start.
+    LookAtPlayer { range: i32 } // Stays put but turns to face the player when they're close.
+}
+
+#[derive(Clone, Debug, Default, Component)]
+pub struct NpcBehaviour {
+    pub mode: NpcBehaviourMode,
+
+    // The patrol path in the coordinates of the level the NPC was spawned in.
+    // Converted to world grid coords once we know where the level is.
+    level_patrol_path: Vec<GridCoords>,
+    patrol_path: Vec<WorldGridCoords>,
+    patrol_index: usize,
+
+    // Where the NPC started, for keeping wanderers close to home.
+    home: Option<WorldGridCoords>,
+
+    idle_timer: Timer
+}
+
+impl NpcBehaviour {
+    // Read the behaviour from the Actor's LDtk fields.
+    pub fn from_entity_info(entity_instance: &EntityInstance, layer_instance: &LayerInstance) -> Self {
+        let mode = match entity_instance.get_string_field("Behaviour").map(|behaviour| behaviour.as_str()) {
+            Ok("Wander") => NpcBehaviourMode::Wander {
+                radius: entity_instance.get_int_field("WanderRadius").copied().unwrap_or(2)
+            },
+            Ok("Patrol") => NpcBehaviourMode::Patrol,
+            Ok("LookAtPlayer") => NpcBehaviourMode::LookAtPlayer {
+                range: entity_instance.get_int_field("LookRange").copied().unwrap_or(DEFAULT_LOOK_RANGE)
+            },
+            Ok("Stationary") | Err(_) => NpcBehaviourMode::Stationary,
+            Ok(unknown) => {
+                println!("Unknown NPC behaviour {}, standing still instead.", unknown);
+                NpcBehaviourMode::Stationary
+            }
+        };
+
+        // Points are in LDtk's grid coordinates (y down), so flip them like bevy_ecs_ldtk does for GridCoords.
+        let level_patrol_path = entity_instance.get_maybe_points_field("PatrolPath")
+            .map(|points| points.iter().flatten().map(|point| ldtk_grid_coords_to_grid_coords(*point, layer_instance.c_hei)).collect())
+            .unwrap_or_default();
+
+        Self {
+            mode,
+            level_patrol_path,
+            idle_timer: Timer::new(WANDER_IDLE_MIN, TimerMode::Once),
+            ..Default::default()
+        }
+    }
+}
+
+// Once an NPC has a position in the world, work out where home is and where the patrol path is.
+pub(crate) fn npc_behaviour_placed(mut query: Query<(&mut NpcBehaviour, &GridCoords, &WorldGridCoords), Added<WorldGridCoords>>) {
+    for (mut npc_behaviour, grid_coords, world_grid_coords) in &mut query {
+        // The difference between level coordinates and world coordinates.
+        let offset = IVec2::new(world_grid_coords.x - grid_coords.x, world_grid_coords.y - grid_coords.y);
+
+        npc_behaviour.patrol_path = npc_behaviour.level_patrol_path.iter().map(|point| WorldGridCoords {
+            x: point.x + offset.x,
+            y: point.y + offset.y,
+            z: world_grid_coords.z
+        }).collect();
+        npc_behaviour.home = Some(*world_grid_coords);
+    }
+}
+
+// Which way to face to look from one tile towards another. None if they're the same tile.
+fn facingdir_towards(from: &WorldGridCoords, to: &WorldGridCoords) -> Option<FacingDir> {
+    let diff = IVec2::new(to.x - from.x, to.y - from.y);
+    if diff == IVec2::ZERO {
+        None
+    } else if diff.x.abs() >= diff.y.abs() {
+        Some(if diff.x > 0 { FacingDir::Right } else { FacingDir::Left })
+    } else {
+        Some(if diff.y > 0 { FacingDir::Up } else { FacingDir::Down })
+    }
+}
+
+fn offset_coords(world_grid_coords: &WorldGridCoords, dir: MoveDir) -> WorldGridCoords {
+    let dir_vec = character::movedir_to_vec(dir);
+    WorldGridCoords { x: world_grid_coords.x + dir_vec.x, y: world_grid_coords.y + dir_vec.y, z: world_grid_coords.z }
+}
+
+// Pick a step that gets us closer to the target, preferring one that isn't blocked.
+fn step_towards(entity: Entity, from: &WorldGridCoords, to: &WorldGridCoords,
+                blocked_tiles_cache: &BlockedTilesCache, terrain_cache: &TerrainCache, flags: &GameFlags,
+                tile_occupancy: &TileOccupancy) -> MoveDir {
+    let diff = IVec2::new(to.x - from.x, to.y - from.y);
+    let horizontal = if diff.x > 0 { MoveDir::Right } else if diff.x < 0 { MoveDir::Left } else { MoveDir::NotMoving };
+    let vertical = if diff.y > 0 { MoveDir::Up } else if diff.y < 0 { MoveDir::Down } else { MoveDir::NotMoving };
+
+    let (first, second) = if diff.x.abs() >= diff.y.abs() { (horizontal, vertical) } else { (vertical, horizontal) };
+
+    for dir in [first, second] {
+        if dir == MoveDir::NotMoving {
+            continue;
+        }
+
+        let step = offset_coords(from, dir);
+        if !blocked_tiles_cache.blocked_tile_locations.contains(&step)
+            && terrain_cache.terrain_at(&step).can_enter(dir, flags)
+            && !tile_occupancy.is_occupied_by_other(&step, entity) {
+            return dir;
+        }
+    }
+
+    // Both blocked, keep trying the way we really want to go. If it's someone in the way they might move.
+    first
+}
+
+fn random_idle_time() -> Duration {
+    let mut rng = rand::thread_rng();
+    Duration::from_secs_f32(rng.gen_range(WANDER_IDLE_MIN.as_secs_f32()..WANDER_IDLE_MAX.as_secs_f32()))
+}
+
+// Decide which way each NPC wants to move this tick.
+fn npc_behaviour_tick(time: Res<Time>,
+                      blocked_tiles_cache: Res<BlockedTilesCache>,
+                      terrain_cache: Res<TerrainCache>,
+                      flags: Res<GameFlags>,
+                      tile_occupancy: Res<TileOccupancy>,
+                      player_query: Query<(&WorldGridCoords, Has<InDialogue>), With<Player>>,
+                      mut npc_query: Query<(Entity, &mut NpcBehaviour, &mut TileMover, &WorldGridCoords, Has<HiddenByFlags>), Without<Player>>) {
+
+    // Nobody wanders off while the player is talking.
+    let (player_grid_coords, player_in_dialogue) = match player_query.get_single() {
+        Ok((player_grid_coords, in_dialogue)) => (Some(*player_grid_coords), in_dialogue),
+        Err(_) => (None, false)
+    };
+
+    for (entity, mut npc_behaviour, mut tile_mover, world_grid_coords, hidden) in &mut npc_query {
+        npc_behaviour.idle_timer.tick(time.delta());
+
+        // Only ask to move for a single tick at a time, otherwise we'd just keep on walking.
+        tile_mover.want_move_dir = MoveDir::NotMoving;
+
+        if player_in_dialogue || hidden || !tile_mover.is_stationary() {
+            continue;
+        }
+
+        match npc_behaviour.mode.clone() {
+            NpcBehaviourMode::Stationary => {},
+            NpcBehaviourMode::Wander { radius } => {
+                if !npc_behaviour.idle_timer.finished() {
+                    continue;
+                }
+
+                let dir = match rand::thread_rng().gen_range(0..4) {
+                    0 => MoveDir::Up,
+                    1 => MoveDir::Down,
+                    2 => MoveDir::Left,
+                    _ => MoveDir::Right
+                };
+
+                // Don't wander too far from home, head back towards it instead.
+                let home = npc_behaviour.home.unwrap_or(*world_grid_coords);
+                let destination = offset_coords(world_grid_coords, dir);
+                tile_mover.want_move_dir = if (destination.x - home.x).abs() > radius || (destination.y - home.y).abs() > radius {
+                    step_towards(entity, world_grid_coords, &home, &blocked_tiles_cache, &terrain_cache, &flags, &tile_occupancy)
+                } else {
+                    dir
+                };
+
+                npc_behaviour.idle_timer = Timer::new(random_idle_time(), TimerMode::Once);
+            },
+            NpcBehaviourMode::Patrol => {
+                if npc_behaviour.patrol_path.is_empty() || !npc_behaviour.idle_timer.finished() {
+                    continue;
+                }
+
+                // Made it to the next point, have a little rest then head to the one after.
+                let target = npc_behaviour.patrol_path[npc_behaviour.patrol_index];
+                if target == *world_grid_coords {
+                    npc_behaviour.patrol_index = (npc_behaviour.patrol_index + 1) % npc_behaviour.patrol_path.len();
+                    npc_behaviour.idle_timer = Timer::new(PATROL_IDLE, TimerMode::Once);
+                    continue;
+                }
+
+                tile_mover.want_move_dir = step_towards(entity, world_grid_coords, &target, &blocked_tiles_cache, &terrain_cache, &flags, &tile_occupancy);
+            },
+            NpcBehaviourMode::LookAtPlayer { range } => {
+                if let Some(player_grid_coords) = player_grid_coords {
+                    let close = player_grid_coords.z == world_grid_coords.z &&
+                                (player_grid_coords.x - world_grid_coords.x).abs() + (player_grid_coords.y - world_grid_coords.y).abs() <= range;
+
+                    if close {
+                        if let Some(dir) = facingdir_towards(world_grid_coords, &player_grid_coords) {
+                            tile_mover.facing_dir = dir;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct NpcPlugin;
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, (npc_behaviour_placed, npc_behaviour_tick)
+                                        .chain()
+                                        .before(character::tile_movement_tick));
+    }
+}
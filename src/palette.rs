@@ -1,9 +1,9 @@
 use std::{collections::HashMap, thread::current};
 
-use bevy::{app::{Plugin, Update}, asset::{Assets, Handle}, color::{palettes, Color, Srgba}, ecs::query::QuerySingleError, log::tracing_subscriber::layer, math::Vec3, prelude::{Added, Bundle, Component, Entity, EventReader, IntoSystemConfigs, Parent, Query, Res, ResMut, Resource, With, Without}};
-use bevy_ecs_ldtk::{app::LdtkEntityAppExt, assets::{LdtkProject, LevelMetadataAccessor}, prelude::LdtkFields, EntityIid, EntityInstance, LdtkEntity, LevelIid};
+use bevy::{app::{Plugin, Update}, color::{palettes, Color, Srgba}, ecs::query::QuerySingleError, log::tracing_subscriber::layer, math::Vec3, prelude::{Added, Bundle, Component, DetectChanges, Entity, EventReader, IntoSystemConfigs, Parent, Query, Res, ResMut, Resource, With, Without}};
+use bevy_ecs_ldtk::{app::LdtkEntityAppExt, EntityIid, EntityInstance, LdtkEntity, LevelIid};
 
-use crate::{character::Player, level_loading::{CurrentLevel, CurrentLevelChangedEvent}, post_process::PaletteSwapPostProcessSettings, util::run_if_ldtk_project_resource_available};
+use crate::{character::Player, level_loading::{CurrentLevel, CurrentLevelChangedEvent}, post_process::PaletteSwapPostProcessSettings, util::run_if_ldtk_project_resource_available, world_data::{level_palette, LdtkProjects, WorldDataErrors}};
 
 // impl Default for Palette {
 //     fn default() -> Self {
@@ -18,17 +18,72 @@ use crate::{character::Player, level_loading::{CurrentLevel, CurrentLevelChanged
 //     }
 // }
 
+// Palettes the player can pick from the options menu instead of using each level's own.
+// Lightest colour first, same as the level Palette fields.
+pub const PALETTE_PRESETS: [(&str, [&str; 4]); 4] = [
+    ("DMG", ["9BBC0F", "8BAC0F", "306230", "0F380F"]),
+    ("POCKET", ["C4CFA1", "8B956D", "4D533C", "1F1F1F"]),
+    ("GREY", ["FFFFFF", "AAAAAA", "555555", "000000"]),
+    ("SEPIA", ["F8E8C8", "C8A878", "886040", "382010"])
+];
+
+// What levels get if their own palette can't be used.
+const DMG_PRESET: usize = 0;
+
+fn preset_colours(preset: usize) -> [Vec3; 4] {
+    PALETTE_PRESETS[preset].1.map(|hex| {
+        let linear = Color::from(Srgba::hex(hex).unwrap()).to_linear();
+        Vec3::new(linear.red, linear.green, linear.blue)
+    })
+}
+
+// Which palette the player wants. None means whatever palette the level has.
+#[derive(Default, Resource)]
+pub struct PaletteChoice {
+    pub preset: Option<usize>,
+    level_colours: Option<[Vec3; 4]> // The current level's palette, to go back to if the preset is turned off.
+}
+
+impl PaletteChoice {
+    // The current level's own palette, whether or not it's being shown.
+    pub fn level_colours(&self) -> Option<[Vec3; 4]> {
+        self.level_colours
+    }
+
+    // Put back a choice from a save. The level's colours get read again once it's loaded anyway,
+    // but this way there's something to show until then.
+    pub fn restore(&mut self, preset: Option<usize>, level_colours: [Vec3; 4]) {
+        self.preset = preset.filter(|preset| *preset < PALETTE_PRESETS.len());
+        self.level_colours = Some(level_colours);
+    }
+}
+
+fn apply_palette_choice(palette_choice: Res<PaletteChoice>,
+                        mut palette_settings_query: Query<&mut PaletteSwapPostProcessSettings>) {
+    if !palette_choice.is_changed() {
+        return;
+    }
+
+    let colours = match palette_choice.preset {
+        Some(preset) => Some(preset_colours(preset)),
+        None => palette_choice.level_colours
+    };
+
+    if let Some(colours) = colours {
+        for mut palette_settings in &mut palette_settings_query {
+            palette_settings.colours = colours;
+        }
+    }
+}
+
 // Update the palette swaping post processing to match whatever palette is in the level the player is in.
 fn check_palette(player_query: Query<(&EntityIid, &CurrentLevel), With<Player>>,
+                 mut palette_choice: ResMut<PaletteChoice>,
                  mut palette_settings_query: Query<&mut PaletteSwapPostProcessSettings>,
                  mut current_level_event_reader: EventReader<CurrentLevelChangedEvent>,
-                 ldtk_project_entities: Query<&Handle<LdtkProject>>,
-                 ldtk_project_assets: Res<Assets<LdtkProject>>) {
+                 ldtk_projects: LdtkProjects,
+                 mut world_data_errors: ResMut<WorldDataErrors>) {
 
-    // Get the ldtk project so that we can get the level data from it.
-    let ldtk_project = ldtk_project_assets.get(ldtk_project_entities.single())
-                        .expect("LdtkProject should be loaded when level is spawned");
-    
     // Get the player entity
     if let Ok((player_entity_iid, _)) = player_query.get_single() {
 
@@ -42,14 +97,23 @@ fn check_palette(player_query: Query<(&EntityIid, &CurrentLevel), With<Player>>,
                 if entity_iid == player_entity_iid {
 
                     // Cool! So the player has entered a new level AND importantly it's actually been loaded too!
-                    let level = ldtk_project.data().get_raw_level_by_iid(level_iid.get()).expect("Level supposedly loaded should exist!");
-                    let colours : [Color; 4] = level.get_colors_field("Palette").expect("All levels should have a palette field!")[0..4].try_into().unwrap();
-
-                    // Get the palette settings entity to change the colors.
-                    if let Ok(mut palette_settings) = palette_settings_query.get_single_mut() {
-                        for (index, colour) in colours.iter().enumerate() {
+                    // If the level's palette is broken, fall back to the good old DMG one.
+                    let level_colours = match ldtk_projects.level(level_iid.get()).and_then(|(_, level)| level_palette(level)) {
+                        Ok(colours) => colours.map(|colour| {
                             let linear = colour.to_linear();
-                            palette_settings.colours[index] = Vec3::new(linear.red, linear.green, linear.blue);
+                            Vec3::new(linear.red, linear.green, linear.blue)
+                        }),
+                        Err(error) => {
+                            world_data_errors.report(error);
+                            preset_colours(DMG_PRESET)
+                        }
+                    };
+                    palette_choice.level_colours = Some(level_colours);
+
+                    // Get the palette settings entity to change the colors. (unless the player picked their own palette)
+                    if palette_choice.preset.is_none() {
+                        if let Ok(mut palette_settings) = palette_settings_query.get_single_mut() {
+                            palette_settings.colours = level_colours;
                         }
                     }
                 }
@@ -62,6 +126,7 @@ fn check_palette(player_query: Query<(&EntityIid, &CurrentLevel), With<Player>>,
 pub struct PalettePlugin;
 impl Plugin for PalettePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Update, check_palette.run_if(run_if_ldtk_project_resource_available));
+        app.init_resource::<PaletteChoice>();
+        app.add_systems(Update, (check_palette.run_if(run_if_ldtk_project_resource_available), apply_palette_choice).chain());
     }
 }
\ No newline at end of file
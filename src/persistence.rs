@@ -0,0 +1,115 @@
+// Remember what's happened to individual LDtk entities.
+// Levels get despawned as soon as the player walks far enough away, and respawned fresh from LDtk when they
+// come back. Anything that should stick (an opened chest, an NPC that's walked somewhere else) gets written
+// down here against the entity's iid, and put back when it's spawned again.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::EntityIid;
+use serde::{Deserialize, Serialize};
+
+use crate::{character::{FacingDir, Player, TileMovedEvent, TileMover}, collision::WorldGridCoords, flags::FlagValue, npc};
+
+// Some common things to remember about entities.
+pub const OPENED: &str = "opened";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EntityState {
+    pub position: Option<WorldGridCoords>, // Where the entity was last, if it's moved from where LDtk put it.
+    pub facing: Option<FacingDir>,
+    pub removed: bool, // Gone for good, don't spawn it again. (picked up items and the like)
+    pub values: HashMap<String, FlagValue> // Anything else, same as the game flags but just for this entity.
+}
+
+// Everything we know about every entity that's changed, by iid.
+// Saved along with the rest of the game.
+#[derive(Clone, Debug, Default, Resource, Serialize, Deserialize)]
+pub struct EntityStates {
+    states: HashMap<String, EntityState>
+}
+
+impl EntityStates {
+    pub fn get(&self, entity_iid: &EntityIid) -> Option<&EntityState> {
+        self.states.get(entity_iid.as_str())
+    }
+
+    pub fn get_mut(&mut self, entity_iid: &EntityIid) -> &mut EntityState {
+        self.states.entry(entity_iid.as_str().to_string()).or_default()
+    }
+
+    // Values that have never been set are false.
+    pub fn get_flag(&self, entity_iid: &EntityIid, name: &str) -> bool {
+        self.get(entity_iid)
+            .and_then(|state| state.values.get(name))
+            .is_some_and(|value| value.is_truthy())
+    }
+
+    pub fn get_value(&self, entity_iid: &EntityIid, name: &str) -> Option<&FlagValue> {
+        self.get(entity_iid).and_then(|state| state.values.get(name))
+    }
+
+    pub fn set_value(&mut self, entity_iid: &EntityIid, name: &str, value: FlagValue) {
+        self.get_mut(entity_iid).values.insert(name.to_string(), value);
+    }
+
+    pub fn is_removed(&self, entity_iid: &EntityIid) -> bool {
+        self.get(entity_iid).is_some_and(|state| state.removed)
+    }
+
+    // Get rid of an entity, and make sure it doesn't come back next time its level is loaded.
+    pub fn remove_permanently(&mut self, entity_iid: &EntityIid) {
+        self.get_mut(entity_iid).removed = true;
+    }
+}
+
+// Entities with this have their state remembered between level loads.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Persistent;
+
+// Put a freshly spawned entity back the way it was left.
+// This runs after NPCs have worked out where home is, so they still wander about where LDtk put them.
+fn restore_entity_state(mut commands: Commands,
+                        entity_states: Res<EntityStates>,
+                        mut query: Query<(Entity, &EntityIid, &mut WorldGridCoords, Option<&mut TileMover>), (With<Persistent>, Without<Player>, Added<WorldGridCoords>)>) {
+    for (entity, entity_iid, mut world_grid_coords, tile_mover) in &mut query {
+        let Some(state) = entity_states.get(entity_iid) else {
+            continue;
+        };
+
+        if state.removed {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        if let Some(position) = state.position {
+            *world_grid_coords = position;
+        }
+
+        if let (Some(facing), Some(mut tile_mover)) = (state.facing, tile_mover) {
+            tile_mover.facing_dir = facing;
+        }
+    }
+}
+
+// Remember where things have walked to.
+fn record_entity_moves(mut entity_states: ResMut<EntityStates>,
+                       mut tile_moved_event_reader: EventReader<TileMovedEvent>,
+                       query: Query<(&EntityIid, &WorldGridCoords, &TileMover), (With<Persistent>, Without<Player>)>) {
+    for tile_moved_event in tile_moved_event_reader.read() {
+        if let Ok((entity_iid, world_grid_coords, tile_mover)) = query.get(tile_moved_event.entity) {
+            let state = entity_states.get_mut(entity_iid);
+            state.position = Some(*world_grid_coords);
+            state.facing = Some(tile_mover.facing_dir);
+        }
+    }
+}
+
+pub struct PersistencePlugin;
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EntityStates>();
+
+        app.add_systems(FixedUpdate, (restore_entity_state.after(npc::npc_behaviour_placed), record_entity_moves));
+    }
+}
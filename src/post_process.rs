@@ -299,9 +299,28 @@ pub struct PaletteSwapPostProcessSettings {
     // -4 is fully light (all palette colors are changed to the lightest).
     pub darkness: i32,
 
+    // Transitions that cover the screen up. (see transition.rs)
+    // 0 is none, 1 is a wipe, 2 is an iris, 3 is a mosaic.
+    pub transition_style: i32,
+    // 0 is clear, 1 is completely covered.
+    pub transition_amount: f32,
+
     // WebGL2 structs must be 16 byte aligned.
     #[cfg(feature = "webgl2")]
-    _webgl2_padding: Vec3,
+    _webgl2_padding: f32,
+}
+
+// The shader picks a palette colour by thresholding the red channel of whatever was drawn.
+// These are greys that sit comfortably inside each of those bands, so anything drawn with them
+// gets recoloured by the level palette. Index 0 is the lightest, 3 the darkest.
+// (These are linear, since that's what the shader ends up sampling.)
+pub fn palette_band_colour(index: usize) -> Color {
+    match index {
+        0 => Color::linear_rgb(1.0, 1.0, 1.0),
+        1 => Color::linear_rgb(0.5, 0.5, 0.5),
+        2 => Color::linear_rgb(0.2, 0.2, 0.2),
+        _ => Color::linear_rgb(0.0, 0.0, 0.0)
+    }
 }
 
 fn update_darkness(mut settings: Query<&mut PaletteSwapPostProcessSettings>, time: Res<Time>) {
@@ -0,0 +1,416 @@
+// Saving and loading the game.
+// Each save slot is a ron file in the saves directory. The file starts with a version number so that
+// saves from a different version of the format are politely refused rather than misread.
+
+use std::{fs, path::PathBuf};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_ecs_ldtk::{ldtk::LdtkJson, LevelIid, LevelSet, Respawn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{audio::{BGMControlEvent, CurrentBgm}, character::{FacingDir, Player, TileMover}, collision::WorldGridCoords, flags::GameFlags, items::Inventory, level_loading::CurrentLevel, palette::PaletteChoice, persistence::EntityStates, post_process::PaletteSwapPostProcessSettings, util::run_if_ldtk_project_resource_available, world_data::LdtkProjects};
+
+const SAVE_DIRECTORY: &str = "saves";
+
+// Bump this whenever the save format changes.
+pub const SAVE_VERSION: u32 = 1;
+
+// Handy while working on things, save and load without going through any menus.
+const QUICK_SAVE_KEY: KeyCode = KeyCode::F5;
+const QUICK_LOAD_KEY: KeyCode = KeyCode::F9;
+const QUICK_SAVE_SLOT: usize = 0;
+
+// Shown when picking a slot, without needing to load the whole save.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveSlotInfo {
+    pub play_time: f64, // In seconds.
+    pub location_name: String
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveData {
+    pub player_position: WorldGridCoords,
+    pub player_facing: FacingDir,
+    pub level_iid: String,
+    pub palette: [[f32; 3]; 4], // The level's own palette, in linear rgb same as the palette post processing uses.
+    pub palette_preset: Option<usize>, // The preset the player picked instead, if they did.
+    pub bgm_path: Option<String>,
+    pub flags: GameFlags,
+    pub entity_states: EntityStates,
+    pub inventory: Inventory
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub info: SaveSlotInfo,
+    pub data: SaveData
+}
+
+// Just enough of a save file to find out which version it is.
+#[derive(Deserialize)]
+struct SaveFileVersion {
+    version: u32
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("Could not access save file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not read save file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("Could not write save file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("Save file version {0} isn't one this game understands (expected {SAVE_VERSION})")]
+    UnsupportedVersion(u32)
+}
+
+pub fn save_path(slot: usize) -> PathBuf {
+    PathBuf::from(SAVE_DIRECTORY).join(format!("slot_{}.ron", slot))
+}
+
+// Read a save file, as long as it was written in the format this game uses.
+fn parse_save(text: &str) -> Result<SaveFile, SaveError> {
+    let version = ron::de::from_str::<SaveFileVersion>(text)?.version;
+    if version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+    Ok(ron::de::from_str(text)?)
+}
+
+pub fn read_save(slot: usize) -> Result<SaveFile, SaveError> {
+    let text = fs::read_to_string(save_path(slot))?;
+    parse_save(&text)
+}
+
+pub fn write_save(slot: usize, save_file: &SaveFile) -> Result<(), SaveError> {
+    fs::create_dir_all(SAVE_DIRECTORY)?;
+    let text = ron::ser::to_string_pretty(save_file, ron::ser::PrettyConfig::default())?;
+    fs::write(save_path(slot), text)?;
+    Ok(())
+}
+
+// What's in a slot, if anything.
+pub fn read_slot_info(slot: usize) -> Option<SaveSlotInfo> {
+    read_save(slot).ok().map(|save_file| save_file.info)
+}
+
+// The level the Player entity is placed in, which is where a new game starts.
+pub fn player_start_level(ldtk_json: &LdtkJson) -> Option<LevelIid> {
+    ldtk_json.toc.iter()
+        .find(|entry| entry.identifier == "Player")
+        .and_then(|entry| entry.instances_data.first())
+        .map(|instance| LevelIid::new(instance.iids.level_iid.clone()))
+}
+
+// The start level out of every project, along with the world entity it belongs to.
+// If more than one project has a Player, the first one in WORLD_PROJECTS wins.
+fn player_start(ldtk_projects: &LdtkProjects) -> Option<(Entity, LevelIid)> {
+    ldtk_projects.iter()
+        .filter_map(|loaded_project| player_start_level(loaded_project.json_data())
+            .map(|start_level| (loaded_project.depth_offset, loaded_project.entity, start_level)))
+        .min_by_key(|(depth_offset, _, _)| *depth_offset)
+        .map(|(_, world_entity, start_level)| (world_entity, start_level))
+}
+
+// How long the game has been played for, across every session.
+#[derive(Default, Debug, Resource)]
+pub struct PlayTime {
+    pub seconds: f64
+}
+
+fn count_play_time(time: Res<Time<Real>>, mut play_time: ResMut<PlayTime>) {
+    play_time.seconds += time.delta_seconds_f64();
+}
+
+#[derive(Event)]
+pub struct SaveGameEvent {
+    pub slot: usize
+}
+
+// Sent once a save has been written (or has failed to be).
+#[derive(Event)]
+pub struct SaveResultEvent {
+    pub slot: usize,
+    pub saved: bool
+}
+
+#[derive(Event)]
+pub struct LoadGameEvent {
+    pub slot: usize
+}
+
+// A save that's been loaded, waiting for the player to exist so it can be put in place.
+#[derive(Default, Resource)]
+struct PendingLoad {
+    data: Option<SaveData>
+}
+
+fn quick_save_load(keys: Res<ButtonInput<KeyCode>>,
+                   mut save_game_event_writer: EventWriter<SaveGameEvent>,
+                   mut load_game_event_writer: EventWriter<LoadGameEvent>) {
+    if keys.just_pressed(QUICK_SAVE_KEY) {
+        save_game_event_writer.send(SaveGameEvent { slot: QUICK_SAVE_SLOT });
+    }
+    if keys.just_pressed(QUICK_LOAD_KEY) {
+        load_game_event_writer.send(LoadGameEvent { slot: QUICK_SAVE_SLOT });
+    }
+}
+
+// If nothing is being loaded, start a new game wherever the Player was placed in LDtk.
+fn start_new_game(pending_load: Res<PendingLoad>,
+                  mut level_set_query: Query<&mut LevelSet>,
+                  ldtk_projects: LdtkProjects) {
+    if pending_load.data.is_some() {
+        return;
+    }
+
+    // Something's already loaded in one of the worlds.
+    if level_set_query.iter().any(|level_set| !level_set.iids.is_empty()) {
+        return;
+    }
+
+    match player_start(&ldtk_projects) {
+        Some((world_entity, start_level)) => {
+            if let Ok(mut level_set) = level_set_query.get_mut(world_entity) {
+                level_set.iids.insert(start_level);
+            }
+        },
+        None => println!("There's no Player in the world, so there's nowhere to start!")
+    }
+}
+
+// Everything that goes in a save besides the player and where they are.
+#[derive(SystemParam)]
+pub(crate) struct SavedResources<'w> {
+    palette_choice: Res<'w, PaletteChoice>,
+    current_bgm: Res<'w, CurrentBgm>,
+    flags: Res<'w, GameFlags>,
+    entity_states: Res<'w, EntityStates>,
+    play_time: Res<'w, PlayTime>
+}
+
+pub(crate) fn save_game(mut save_game_event_reader: EventReader<SaveGameEvent>,
+             mut save_result_event_writer: EventWriter<SaveResultEvent>,
+             player_query: Query<(&WorldGridCoords, &TileMover, &CurrentLevel, &Inventory), With<Player>>,
+             palette_settings_query: Query<&PaletteSwapPostProcessSettings>,
+             saved: SavedResources,
+             ldtk_projects: LdtkProjects) {
+    for event in save_game_event_reader.read() {
+        let Ok((world_grid_coords, tile_mover, current_level, inventory)) = player_query.get_single() else {
+            println!("Can't save, there's no player!");
+            save_result_event_writer.send(SaveResultEvent { slot: event.slot, saved: false });
+            continue;
+        };
+
+        let Some(level_iid) = &current_level.level_iid else {
+            println!("Can't save, the player isn't in a level!");
+            save_result_event_writer.send(SaveResultEvent { slot: event.slot, saved: false });
+            continue;
+        };
+
+        // Level names are good enough for location names for now.
+        let location_name = ldtk_projects.level(level_iid.get())
+            .map(|(_, level)| level.identifier.replace('_', " "))
+            .unwrap_or_default();
+
+        // The level's own colours, even if a preset is covering them up right now.
+        let palette = saved.palette_choice.level_colours()
+            .or_else(|| palette_settings_query.get_single().ok().map(|settings| settings.colours))
+            .map(|colours| colours.map(|colour| colour.to_array()))
+            .unwrap_or_default();
+
+        let save_file = SaveFile {
+            version: SAVE_VERSION,
+            info: SaveSlotInfo {
+                play_time: saved.play_time.seconds,
+                location_name
+            },
+            data: SaveData {
+                player_position: *world_grid_coords,
+                player_facing: tile_mover.facing_dir,
+                level_iid: level_iid.get().clone(),
+                palette,
+                palette_preset: saved.palette_choice.preset,
+                bgm_path: saved.current_bgm.path.clone(),
+                flags: saved.flags.clone(),
+                entity_states: saved.entity_states.clone(),
+                inventory: inventory.clone()
+            }
+        };
+
+        let saved = match write_save(event.slot, &save_file) {
+            Ok(()) => {
+                println!("Saved to slot {}", event.slot);
+                true
+            },
+            Err(error) => {
+                println!("Failed to save to slot {}: {}", event.slot, error);
+                false
+            }
+        };
+        save_result_event_writer.send(SaveResultEvent { slot: event.slot, saved });
+    }
+}
+
+// The events load_game handles and sends, and everything a save gets loaded back into.
+#[derive(SystemParam)]
+struct LoadedResources<'w, 's> {
+    load_game_event_reader: EventReader<'w, 's, LoadGameEvent>,
+    bgm_control_event_writer: EventWriter<'w, BGMControlEvent>,
+    pending_load: ResMut<'w, PendingLoad>,
+    flags: ResMut<'w, GameFlags>,
+    entity_states: ResMut<'w, EntityStates>,
+    play_time: ResMut<'w, PlayTime>,
+    current_bgm: ResMut<'w, CurrentBgm>,
+    palette_choice: ResMut<'w, PaletteChoice>
+}
+
+fn load_game(mut commands: Commands,
+             mut loaded: LoadedResources,
+             mut level_set_query: Query<&mut LevelSet>,
+             level_query: Query<Entity, With<LevelIid>>,
+             player_query: Query<Entity, With<Player>>,
+             asset_server: Res<AssetServer>,
+             ldtk_projects: LdtkProjects) {
+    for event in loaded.load_game_event_reader.read() {
+        let save_file = match read_save(event.slot) {
+            Ok(save_file) => save_file,
+            Err(error) => {
+                println!("Failed to load slot {}: {}", event.slot, error);
+                continue;
+            }
+        };
+
+        let data = save_file.data;
+        *loaded.flags = data.flags.clone();
+        *loaded.entity_states = data.entity_states.clone();
+        loaded.play_time.seconds = save_file.info.play_time;
+
+        loaded.palette_choice.restore(data.palette_preset, data.palette.map(Vec3::from_array));
+
+        if let Some(bgm_path) = &data.bgm_path {
+            loaded.bgm_control_event_writer.send(BGMControlEvent::Change(asset_server.load(bgm_path.clone())));
+        }
+        loaded.current_bgm.path = data.bgm_path.clone();
+
+        // Load the level the save was in. The player is Worldly so it only gets spawned along with the level it
+        // was placed in, if it doesn't exist yet we'll need that level too. (it gets unloaded again once the player moves over)
+        // Anything already spawned could be out of date with the flags and entity states we just loaded.
+        for level_entity in &level_query {
+            commands.entity(level_entity).insert(Respawn);
+        }
+
+        // Each level goes in the level set of whichever world it's from.
+        for mut level_set in &mut level_set_query {
+            level_set.iids.clear();
+        }
+
+        match ldtk_projects.level(&data.level_iid) {
+            Ok((loaded_project, _)) => {
+                if let Ok(mut level_set) = level_set_query.get_mut(loaded_project.entity) {
+                    level_set.iids.insert(LevelIid::new(data.level_iid.clone()));
+                }
+            },
+            Err(error) => println!("The save is in a level that doesn't exist any more: {}", error)
+        }
+
+        if player_query.is_empty() {
+            if let Some((world_entity, start_level)) = player_start(&ldtk_projects) {
+                if let Ok(mut level_set) = level_set_query.get_mut(world_entity) {
+                    level_set.iids.insert(start_level);
+                }
+            }
+        }
+
+        println!("Loaded slot {}", event.slot);
+        loaded.pending_load.data = Some(data);
+    }
+}
+
+// Once the player exists, put them where the save says they were.
+fn place_loaded_player(mut pending_load: ResMut<PendingLoad>,
+                       mut player_query: Query<(&mut WorldGridCoords, &mut TileMover, &mut CurrentLevel, &mut Inventory), With<Player>>) {
+    let Some(data) = &pending_load.data else {
+        return;
+    };
+
+    if let Ok((mut world_grid_coords, mut tile_mover, mut current_level, mut inventory)) = player_query.get_single_mut() {
+        *world_grid_coords = data.player_position;
+        tile_mover.facing_dir = data.player_facing;
+        *inventory = data.inventory.clone();
+
+        // Forget which level we were in, so it gets worked out again and the neighbouring levels get loaded.
+        current_level.level_iid = None;
+
+        pending_load.data = None;
+    }
+}
+
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayTime>();
+        app.init_resource::<PendingLoad>();
+        app.add_event::<SaveGameEvent>();
+        app.add_event::<SaveResultEvent>();
+        app.add_event::<LoadGameEvent>();
+
+        // Saves hold the music and palette as well, which are there even without the audio and palette plugins.
+        app.init_resource::<CurrentBgm>();
+        app.init_resource::<PaletteChoice>();
+        app.add_event::<BGMControlEvent>();
+
+        app.add_systems(Update, (count_play_time, quick_save_load));
+        app.add_systems(Update, (save_game, load_game).after(quick_save_load).run_if(run_if_ldtk_project_resource_available));
+        app.add_systems(FixedUpdate, (start_new_game, place_loaded_player).run_if(run_if_ldtk_project_resource_available));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs_ldtk::EntityIid;
+
+    use super::*;
+
+    // A save file as the game writes it.
+    fn save_text(version: u32) -> String {
+        format!(r#"(version: {}, info: (play_time: 60.0, location_name: "Town"), data: (
+                 player_position: (x: 2, y: -3, z: 0), player_facing: Left, level_iid: "level",
+                 palette: ((1.0, 1.0, 1.0), (0.6, 0.6, 0.6), (0.3, 0.3, 0.3), (0.0, 0.0, 0.0)), palette_preset: Some(2),
+                 bgm_path: Some("music/town.ogg"), flags: (flags: {{"met_mayor": Bool(true), "badges": Int(2)}}),
+                 entity_states: (states: {{"chest": (position: None, facing: None, removed: true, values: {{}})}}),
+                 inventory: (slots: [(item: "potion", count: 3)])))"#, version)
+    }
+
+    fn assert_data(save_file: &SaveFile) {
+        assert_eq!(save_file.version, SAVE_VERSION);
+        assert_eq!(save_file.info.location_name, "Town");
+        assert_eq!(save_file.data.player_position, WorldGridCoords { x: 2, y: -3, z: 0 });
+        assert_eq!(save_file.data.player_facing, FacingDir::Left);
+        assert_eq!(save_file.data.level_iid, "level");
+        assert_eq!(save_file.data.palette[1], [0.6, 0.6, 0.6]);
+        assert_eq!(save_file.data.palette_preset, Some(2));
+        assert_eq!(save_file.data.bgm_path.as_deref(), Some("music/town.ogg"));
+        assert!(save_file.data.flags.get("met_mayor"));
+        assert_eq!(save_file.data.flags.get_int("badges"), 2);
+        assert!(save_file.data.entity_states.is_removed(&EntityIid::new("chest")));
+        assert_eq!(save_file.data.inventory.count("potion"), 3);
+    }
+
+    #[test]
+    fn reads_a_save_as_written() {
+        let save_file = parse_save(&save_text(SAVE_VERSION)).unwrap();
+        assert_data(&save_file);
+
+        let text = ron::ser::to_string_pretty(&save_file, ron::ser::PrettyConfig::default()).unwrap();
+        assert_data(&parse_save(&text).unwrap());
+    }
+
+    #[test]
+    fn refuses_versions_it_doesnt_know() {
+        assert!(matches!(parse_save(&save_text(0)), Err(SaveError::UnsupportedVersion(0))));
+        assert!(matches!(parse_save(&save_text(SAVE_VERSION + 1)), Err(SaveError::UnsupportedVersion(_))));
+    }
+}
@@ -0,0 +1,222 @@
+// What each tile of the Collision IntGrid layer is made of.
+// Most tiles are either floor or wall, but some have their own rules about who can walk on them and how.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{app::LdtkIntCellAppExt, prelude::*, LevelEvent};
+use rand::Rng;
+
+use crate::{character::{FacingDir, MoveDir, Player, TileMovedEvent}, collision::{self, LevelTileIndex, WorldGridCoords, WorldGridCoordsRequired}, flags::GameFlags, util};
+
+// The values used in the Collision IntGrid layer.
+const WALL_GRID_CELL: i32 = 1;
+const LEDGE_DOWN_GRID_CELL: i32 = 2;
+const LEDGE_LEFT_GRID_CELL: i32 = 3;
+const LEDGE_RIGHT_GRID_CELL: i32 = 4;
+const LEDGE_UP_GRID_CELL: i32 = 5;
+const WATER_GRID_CELL: i32 = 6;
+const COUNTER_GRID_CELL: i32 = 7;
+const GRASS_GRID_CELL: i32 = 8;
+
+// The flag that lets the player get into water.
+pub const SWIM_FLAG: &str = "can_swim";
+
+// The chance of something jumping out at you on each step through grass, unless it's been changed.
+const DEFAULT_GRASS_ENCOUNTER_CHANCE: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TerrainType {
+    #[default]
+    Floor,
+    Wall,
+    Ledge(FacingDir), // Can only be crossed going in this direction. (you can jump down, but not climb back up)
+    Water, // Needs the SWIM_FLAG to go in.
+    Counter, // Can't be walked on, but things can be interacted with across it.
+    Grass // Walked through like floor, but random encounters happen here.
+}
+
+impl TerrainType {
+    pub fn from_int_grid_value(value: i32) -> Self {
+        match value {
+            WALL_GRID_CELL => TerrainType::Wall,
+            LEDGE_DOWN_GRID_CELL => TerrainType::Ledge(FacingDir::Down),
+            LEDGE_LEFT_GRID_CELL => TerrainType::Ledge(FacingDir::Left),
+            LEDGE_RIGHT_GRID_CELL => TerrainType::Ledge(FacingDir::Right),
+            LEDGE_UP_GRID_CELL => TerrainType::Ledge(FacingDir::Up),
+            WATER_GRID_CELL => TerrainType::Water,
+            COUNTER_GRID_CELL => TerrainType::Counter,
+            GRASS_GRID_CELL => TerrainType::Grass,
+            _ => TerrainType::Floor
+        }
+    }
+
+    // Can something moving in this direction step onto a tile of this terrain?
+    pub fn can_enter(&self, dir: MoveDir, flags: &GameFlags) -> bool {
+        match self {
+            TerrainType::Floor | TerrainType::Grass => true,
+            TerrainType::Wall | TerrainType::Counter => false,
+            TerrainType::Ledge(ledge_dir) => matches!((ledge_dir, dir),
+                (FacingDir::Up, MoveDir::Up) |
+                (FacingDir::Down, MoveDir::Down) |
+                (FacingDir::Left, MoveDir::Left) |
+                (FacingDir::Right, MoveDir::Right)),
+            TerrainType::Water => flags.get(SWIM_FLAG)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Component)]
+pub struct Terrain {
+    pub terrain_type: TerrainType
+}
+
+// A tile of the Collision layer. Whether it can be walked on is up to its terrain, walls don't need Blocking as well.
+#[derive(Clone, Debug, Default, Bundle)]
+struct TerrainTileBundle {
+    terrain: Terrain,
+    world_grid_coords_required: WorldGridCoordsRequired
+}
+
+impl LdtkIntCell for TerrainTileBundle {
+    fn bundle_int_cell(int_grid_cell: IntGridCell, _layer_instance: &LayerInstance) -> Self {
+        Self {
+            terrain: Terrain { terrain_type: TerrainType::from_int_grid_value(int_grid_cell.value) },
+            ..Default::default()
+        }
+    }
+}
+
+// Lookup of the terrain at each tile, kept up to date as levels come and go.
+// Anything not in here is plain floor.
+#[derive(Default, Resource)]
+pub struct TerrainCache {
+    // Every terrain tile on each spot, newest last. Levels can overlap for a moment while one is
+    // replacing another, so a tile going away only takes its own terrain with it.
+    terrain: HashMap<WorldGridCoords, Vec<(Entity, TerrainType)>>,
+
+    // Where each terrain tile is and which level it's from, so we know what to remove when it goes away.
+    tiles: LevelTileIndex
+}
+
+impl TerrainCache {
+    pub fn terrain_at(&self, world_grid_coords: &WorldGridCoords) -> TerrainType {
+        self.terrain.get(world_grid_coords)
+            .and_then(|tiles| tiles.last())
+            .map(|(_, terrain_type)| *terrain_type)
+            .unwrap_or_default()
+    }
+
+    pub fn insert(&mut self, entity: Entity, world_grid_coords: WorldGridCoords, terrain_type: TerrainType, level_iid: Option<LevelIid>) {
+        // Already known about, forget the old spot first.
+        if let Some(old_world_grid_coords) = self.tiles.insert(entity, world_grid_coords, level_iid) {
+            self.forget(entity, old_world_grid_coords);
+        }
+
+        self.terrain.entry(world_grid_coords).or_default().push((entity, terrain_type));
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(world_grid_coords) = self.tiles.remove(entity) {
+            self.forget(entity, world_grid_coords);
+        }
+    }
+
+    pub fn evict_level(&mut self, level_iid: &LevelIid) {
+        for (entity, world_grid_coords) in self.tiles.evict_level(level_iid) {
+            self.forget(entity, world_grid_coords);
+        }
+    }
+
+    // Take a tile's terrain off its spot. (there's hardly ever more than one on a spot, so the retain is cheap)
+    fn forget(&mut self, entity: Entity, world_grid_coords: WorldGridCoords) {
+        if let Some(tiles) = self.terrain.get_mut(&world_grid_coords) {
+            tiles.retain(|(tile_entity, _)| *tile_entity != entity);
+            if tiles.is_empty() {
+                self.terrain.remove(&world_grid_coords);
+            }
+        }
+    }
+}
+
+fn add_terrain_tiles(mut terrain_cache: ResMut<TerrainCache>,
+                     terrain_tiles: Query<(Entity, &Terrain, &WorldGridCoords, Option<&Parent>), Added<WorldGridCoords>>,
+                     parent_query: Query<&Parent>,
+                     level_query: Query<&LevelIid>) {
+    for (entity, terrain, world_grid_coords, parent) in &terrain_tiles {
+        let level_iid = collision::level_of(parent, &parent_query, &level_query);
+        terrain_cache.insert(entity, *world_grid_coords, terrain.terrain_type, level_iid);
+    }
+}
+
+fn remove_terrain_tiles(mut terrain_cache: ResMut<TerrainCache>,
+                        mut removed_terrain: RemovedComponents<Terrain>,
+                        mut level_event_reader: EventReader<LevelEvent>) {
+    for level_event in level_event_reader.read() {
+        if let LevelEvent::Despawned(level_iid) = level_event {
+            terrain_cache.evict_level(level_iid);
+        }
+    }
+
+    for entity in removed_terrain.read() {
+        terrain_cache.remove(entity);
+    }
+}
+
+// How likely the player is to run into something on each step through grass.
+#[derive(Resource)]
+pub struct GrassEncounters {
+    pub chance: f32 // 0 is never, 1 is every step.
+}
+
+impl Default for GrassEncounters {
+    fn default() -> Self {
+        Self { chance: DEFAULT_GRASS_ENCOUNTER_CHANCE }
+    }
+}
+
+// Sent when the player steps into grass and something jumps out.
+#[derive(Clone, Debug, Event)]
+pub struct GrassEncounterEvent {
+    pub entity: Entity,
+    pub world_grid_coords: WorldGridCoords
+}
+
+fn grass_encounter(terrain_cache: Res<TerrainCache>,
+                   grass_encounters: Res<GrassEncounters>,
+                   mut tile_moved_event_reader: EventReader<TileMovedEvent>,
+                   mut grass_encounter_event_writer: EventWriter<GrassEncounterEvent>,
+                   player_query: Query<&WorldGridCoords, With<Player>>) {
+    for tile_moved_event in tile_moved_event_reader.read() {
+        // Go by where the move ended, the player might have set off again since.
+        let Ok(world_grid_coords) = player_query.get(tile_moved_event.entity) else {
+            continue;
+        };
+        let world_grid_coords = WorldGridCoords { x: tile_moved_event.pos.x, y: tile_moved_event.pos.y, z: world_grid_coords.z };
+
+        if terrain_cache.terrain_at(&world_grid_coords) == TerrainType::Grass
+            && rand::thread_rng().gen::<f32>() < grass_encounters.chance {
+            grass_encounter_event_writer.send(GrassEncounterEvent {
+                entity: tile_moved_event.entity,
+                world_grid_coords
+            });
+        }
+    }
+}
+
+pub struct TerrainPlugin;
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        // Every value in the Collision layer gets its own terrain.
+        for value in [WALL_GRID_CELL, COUNTER_GRID_CELL, LEDGE_DOWN_GRID_CELL, LEDGE_LEFT_GRID_CELL, LEDGE_RIGHT_GRID_CELL, LEDGE_UP_GRID_CELL, WATER_GRID_CELL, GRASS_GRID_CELL] {
+            app.register_ldtk_int_cell::<TerrainTileBundle>(value);
+        }
+
+        app.init_resource::<TerrainCache>();
+        app.init_resource::<GrassEncounters>();
+        app.add_event::<GrassEncounterEvent>();
+
+        app.add_systems(FixedUpdate, add_terrain_tiles.after(crate::collision::world_grid_coords_required).run_if(util::run_if_ldtk_project_resource_available));
+        app.add_systems(FixedUpdate, (remove_terrain_tiles, grass_encounter));
+    }
+}
@@ -0,0 +1,250 @@
+// Screen transitions, for hiding the screen while something happens behind it (like warping to another level)
+// and revealing it again afterwards.
+// Anything can start one, just grab the ScreenTransition resource, call fade_out, wait for is_covered,
+// do whatever needs hiding, then call fade_in.
+// If whatever's happening takes a while (like waiting on a level to load), call start_loading and a loading
+// indicator gets shown while the screen's covered.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{font::{PixelText, PixelTextBundle}, post_process::{palette_band_colour, PaletteSwapPostProcessSettings}};
+
+// How many steps of darkness it takes to get to a single colour.
+const DARKNESS_STEPS: f32 = 4.0;
+
+// How long the screen has to have been covered while loading before the loading indicator shows up.
+// Long enough that waiting a frame or two doesn't flash it up.
+const LOADING_INDICATOR_DELAY: Duration = Duration::from_millis(250);
+
+// The view is 160x144, with the camera in the middle.
+const VIEW_SIZE: Vec2 = Vec2::new(160.0, 144.0);
+const LOADING_INDICATOR_Z: f32 = 120.0; // Over the top of everything, menus included.
+const LOADING_TEXT: &str = "Loading...";
+const LOADING_TEXT_POSITION: Vec2 = Vec2::new(-72.0, -56.0); // Bottom left corner.
+
+// The ways the screen can be covered up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub enum TransitionStyle {
+    #[default]
+    FadeBlack, // Step the palette down to the darkest colour.
+    FadeWhite, // Step the palette up to the lightest colour.
+    Wipe, // The darkest colour sweeps across from the left.
+    Iris, // A circle closing in on the middle of the screen.
+    Mosaic // Everything gets blockier, and darker.
+}
+
+impl TransitionStyle {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "FadeBlack" => Some(TransitionStyle::FadeBlack),
+            "FadeWhite" => Some(TransitionStyle::FadeWhite),
+            "Wipe" => Some(TransitionStyle::Wipe),
+            "Iris" => Some(TransitionStyle::Iris),
+            "Mosaic" => Some(TransitionStyle::Mosaic),
+            _ => None
+        }
+    }
+
+    // What the shader calls this style.
+    fn shader_style(&self) -> i32 {
+        match self {
+            TransitionStyle::FadeBlack | TransitionStyle::FadeWhite => 0,
+            TransitionStyle::Wipe => 1,
+            TransitionStyle::Iris => 2,
+            TransitionStyle::Mosaic => 3
+        }
+    }
+
+    // How much to darken (or lighten) the palette by, part way through.
+    fn darkness(&self, amount: f32) -> i32 {
+        let steps = (amount * DARKNESS_STEPS) as i32;
+        match self {
+            TransitionStyle::FadeBlack | TransitionStyle::Mosaic => steps,
+            TransitionStyle::FadeWhite => -steps,
+            TransitionStyle::Wipe | TransitionStyle::Iris => 0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum TransitionPhase {
+    #[default]
+    Clear,
+    Out,
+    Covered,
+    In
+}
+
+// The transition that's currently happening, if there is one.
+#[derive(Default, Resource)]
+pub struct ScreenTransition {
+    style: TransitionStyle,
+    phase: TransitionPhase,
+    timer: Timer,
+    loading: Option<Timer> // How long we've been loading for, while covered.
+}
+
+impl ScreenTransition {
+    // Start covering up the screen.
+    pub fn fade_out(&mut self, style: TransitionStyle, duration: Duration) {
+        self.style = style;
+        self.phase = TransitionPhase::Out;
+        self.timer = Timer::new(duration, TimerMode::Once);
+        self.loading = None;
+    }
+
+    // Start revealing the screen again, the same way it was covered up.
+    pub fn fade_in(&mut self, duration: Duration) {
+        self.phase = TransitionPhase::In;
+        self.timer = Timer::new(duration, TimerMode::Once);
+        self.loading = None;
+    }
+
+    // Something's taking longer than the transition did. Until the screen starts fading back in,
+    // the loading indicator gets shown. (after a moment, and if it's turned on)
+    pub fn start_loading(&mut self) {
+        if self.loading.is_none() {
+            self.loading = Some(Timer::new(LOADING_INDICATOR_DELAY, TimerMode::Once));
+        }
+    }
+
+    // Whether it's been loading long enough to say so.
+    fn loading_for_a_while(&self) -> bool {
+        self.is_covered() && self.loading.as_ref().is_some_and(|loading| loading.finished())
+    }
+
+    // Completely covered, so anything can happen without being seen.
+    pub fn is_covered(&self) -> bool {
+        self.phase == TransitionPhase::Covered
+    }
+
+    // Nothing happening.
+    pub fn is_clear(&self) -> bool {
+        self.phase == TransitionPhase::Clear
+    }
+
+    // How much of the screen is covered, from 0 to 1.
+    pub fn amount(&self) -> f32 {
+        match self.phase {
+            TransitionPhase::Clear => 0.0,
+            TransitionPhase::Out => self.timer.fraction(),
+            TransitionPhase::Covered => 1.0,
+            TransitionPhase::In => 1.0 - self.timer.fraction()
+        }
+    }
+}
+
+// Whether to show anything while loading behind a covered screen, or just leave it covered.
+#[derive(Resource)]
+pub struct LoadingIndicatorSettings {
+    pub enabled: bool
+}
+
+impl Default for LoadingIndicatorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true
+        }
+    }
+}
+
+// The loading indicator, which covers the screen itself so the text on it doesn't get covered up too.
+#[derive(Component)]
+pub struct LoadingIndicator;
+
+fn tick_transition(time: Res<Time>,
+                   mut screen_transition: ResMut<ScreenTransition>,
+                   loading_indicator_settings: Res<LoadingIndicatorSettings>,
+                   mut palette_settings: Query<&mut PaletteSwapPostProcessSettings>) {
+    // Don't touch the settings when nothing's happening, so they're left alone outside of transitions.
+    if screen_transition.is_clear() {
+        return;
+    }
+
+    screen_transition.timer.tick(time.delta());
+    if screen_transition.timer.finished() {
+        screen_transition.phase = match screen_transition.phase {
+            TransitionPhase::Out => TransitionPhase::Covered,
+            TransitionPhase::In => TransitionPhase::Clear,
+            phase => phase
+        };
+    }
+
+    if screen_transition.is_covered() {
+        if let Some(loading) = &mut screen_transition.loading {
+            loading.tick(time.delta());
+        }
+    }
+
+    // Once it's clear again, everything goes back to normal.
+    // The same goes for while the loading indicator is covering the screen instead.
+    let showing_loading = loading_indicator_settings.enabled && screen_transition.loading_for_a_while();
+    let amount = if showing_loading { 0.0 } else { screen_transition.amount() };
+    let style = screen_transition.style;
+    let clear = screen_transition.is_clear() || showing_loading;
+    for mut settings in &mut palette_settings {
+        settings.darkness = style.darkness(amount);
+        settings.transition_style = if clear { 0 } else { style.shader_style() };
+        settings.transition_amount = amount;
+    }
+}
+
+// Put the loading indicator up once it's been loading a while, and take it down again once it's done.
+fn show_loading_indicator(mut commands: Commands,
+                          screen_transition: Res<ScreenTransition>,
+                          loading_indicator_settings: Res<LoadingIndicatorSettings>,
+                          camera_query: Query<Entity, With<Camera2d>>,
+                          loading_indicator_query: Query<Entity, With<LoadingIndicator>>) {
+    let showing_loading = loading_indicator_settings.enabled && screen_transition.loading_for_a_while();
+
+    if !showing_loading {
+        for loading_indicator_entity in &loading_indicator_query {
+            commands.entity(loading_indicator_entity).despawn_recursive();
+        }
+        return;
+    }
+
+    if !loading_indicator_query.is_empty() {
+        return;
+    }
+    let Ok(camera_entity) = camera_query.get_single() else {
+        return;
+    };
+
+    // The same colour the transition covered the screen with, and the text in the opposite one.
+    let (background, foreground) = if screen_transition.style == TransitionStyle::FadeWhite { (0, 3) } else { (3, 0) };
+
+    // It hangs off the camera so it stays put on screen.
+    commands.entity(camera_entity).with_children(|parent| {
+        parent.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: palette_band_colour(background),
+                    custom_size: Some(VIEW_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, LOADING_INDICATOR_Z),
+                ..default()
+            },
+            LoadingIndicator
+        )).with_children(|parent| {
+            parent.spawn(PixelTextBundle {
+                text: PixelText::coloured(LOADING_TEXT, foreground),
+                spatial: SpatialBundle::from_transform(Transform::from_xyz(LOADING_TEXT_POSITION.x, LOADING_TEXT_POSITION.y, 0.1))
+            });
+        });
+    });
+}
+
+pub struct TransitionPlugin;
+impl Plugin for TransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenTransition>();
+        app.init_resource::<LoadingIndicatorSettings>();
+
+        app.add_systems(Update, (tick_transition, show_loading_indicator).chain());
+    }
+}
@@ -1,5 +1,7 @@
-use bevy::{asset::{Assets, Handle}, math::IVec2, prelude::{Query, Res}};
-use bevy_ecs_ldtk::{assets::LdtkProject, ldtk::Level};
+use bevy::math::IVec2;
+use bevy_ecs_ldtk::ldtk::Level;
+
+use crate::world_data::LdtkProjects;
 
 const TILE_GRID_SIZE: IVec2 = IVec2::new(16, 16);
 
@@ -8,8 +10,8 @@ pub fn get_level_origin_grid_coord(level: &Level) {
     IVec2::new(level.world_x, 0 - level.world_y - level.px_hei) / TILE_GRID_SIZE;
 }
 
-pub fn run_if_ldtk_project_resource_available(ldtk_project_assets: Res<Assets<LdtkProject>>,
-                                          ldtk_project_entities: Query<&Handle<LdtkProject>>) -> bool {
+pub fn run_if_ldtk_project_resource_available(ldtk_projects: LdtkProjects) -> bool {
 
-    ldtk_project_assets.get(ldtk_project_entities.single()).is_some()
+    // Wait for all of them, a level might have neighbours or warps in any project.
+    ldtk_projects.all_loaded()
 }
\ No newline at end of file
@@ -1,25 +1,139 @@
 use std::{collections::HashMap, time::Duration};
 
-use bevy::{app::{FixedUpdate, Plugin}, asset::{Assets, Handle}, prelude::{run_once, Bundle, Commands, Component, Entity, EventReader, IntoSystemConfigs, Query, Res, ResMut, Resource, With, Without}, time::{Time, Timer, TimerMode}};
-use bevy_ecs_ldtk::{app::LdtkEntityAppExt, assets::{InternalLevels, LdtkJsonWithMetadata, LdtkProject}, prelude::LdtkFields, EntityIid, EntityInstance, GridCoords, LdtkEntity, LevelIid, LevelSelection};
+use bevy::{app::{FixedUpdate, Plugin, Update}, asset::{AssetServer, Assets, Handle}, math::UVec2, prelude::{default, run_once, Added, Bundle, Commands, Component, Entity, Event, EventReader, EventWriter, Image, IntoSystemConfigs, Query, Res, ResMut, Resource, SpriteBundle, Visibility, With, Without}, sprite::{TextureAtlas, TextureAtlasLayout}, time::{Time, Timer, TimerMode}};
+use bevy_ecs_ldtk::{app::LdtkEntityAppExt, assets::{InternalLevels, LdtkJsonWithMetadata}, ldtk::{LayerInstance, LdtkJson, LdtkTocInstanceData, TilesetDefinition}, prelude::{LdtkEntity, LdtkFields}, EntityIid, EntityInstance, GridCoords, LevelIid, LevelSelection};
+use thiserror::Error;
+
+use crate::{character::{self, facingdir_to_movedir, movedir_to_facingdir, movedir_to_vec, FacingDir, MoveDir, Player, TileMovedEvent, TileMover}, collision::{self, Blocking, WorldGridCoords, WorldGridCoordsRequired, TILE_GRID_SIZE}, flags::{FlagCondition, FlagConditionParseError, GameFlags}, level_loading::PreloadLevelEvent, transition::{ScreenTransition, TransitionStyle}, util::run_if_ldtk_project_resource_available, world_data::LdtkProjects};
+
+// How a warp gets used.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WarpKind {
+    #[default]
+    StepOn, // Walk onto it. (stairs, cave entrances)
+    WalkInto, // Try to walk into it from the tile next to it. (a door in a wall)
+    Door // Same as WalkInto, but the door opens first.
+}
+
+impl WarpKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "StepOn" => Some(WarpKind::StepOn),
+            "WalkInto" => Some(WarpKind::WalkInto),
+            "Door" => Some(WarpKind::Door),
+            _ => None
+        }
+    }
 
-use crate::{character::{Player, TileMovedEvent}, collision::{self, WorldGridCoords, WorldGridCoordsRequired, TILE_GRID_SIZE}, post_process::PaletteSwapPostProcessSettings, util::run_if_ldtk_project_resource_available};
+    fn is_walk_into(&self) -> bool {
+        matches!(self, WarpKind::WalkInto | WarpKind::Door)
+    }
+}
 
 // The target of a warp. 
 #[derive(Clone, Debug)]
 struct WarpTarget {
     level_iid: LevelIid, // The level to warp to.
-    entity_iid: EntityIid // The entity id of the WarpTargetTile.
+    entity_iid: EntityIid, // The entity id of the WarpTargetTile.
+    project: String, // The project the target is in. (see world_data::WORLD_PROJECTS)
+    requires: Option<FlagCondition>, // The warp only works while this holds. (locked doors and the like)
+    kind: WarpKind,
+    transition: TransitionStyle // How the screen is covered up while warping.
 }
 
-// How long do we fade out before actually warping?
+impl WarpTarget {
+    fn unlocked(&self, flags: &GameFlags) -> bool {
+        self.requires.as_ref().is_none_or(|condition| condition.evaluate(flags))
+    }
+}
+
+// Where a WarpTarget puts the player, and what they do when they get there.
+#[derive(Clone, Copy, Debug)]
+struct WarpArrival {
+    position: WorldGridCoords,
+    facing: Option<FacingDir>, // Keep facing the same way if there isn't one.
+    walk_out: bool // Take a step in the facing direction after arriving, e.g. out of a door.
+}
+
+// How long do we fade out before actually warping, and back in once we're there?
 const WARP_FADE_OUT_TIME: Duration = Duration::from_millis(500);
+const WARP_FADE_IN_TIME: Duration = Duration::from_millis(500);
+
+// Doors take a moment to open before we start fading out.
+const DOOR_OPEN_TIME: Duration = Duration::from_millis(300);
+const DOOR_PATH: &str = "door.png";
+const DOOR_FRAMES: usize = 3; // Closed, half open, open.
+const DOOR_CLOSED_FRAME: usize = 0;
 
 // Specifies that the player is locked and cannot be moved due to a pending warp.
 #[derive(Clone, Component)]
-struct WarpPending {
+pub struct WarpPending {
     target: WarpTarget,
-    fade_out_timer: Timer
+    door: Option<(WorldGridCoords, Timer)>, // The door we're waiting on to open, if there is one.
+    fading: bool, // Started covering the screen up.
+    warped: bool // Moved to the target, and waiting for its level to load.
+}
+
+impl WarpPending {
+    fn new(target: WarpTarget, door: Option<WorldGridCoords>) -> Self {
+        Self {
+            target,
+            door: door.map(|door| (door, Timer::new(DOOR_OPEN_TIME, TimerMode::Once))),
+            fading: false,
+            warped: false
+        }
+    }
+}
+
+// Take a step out after arriving somewhere.
+#[derive(Clone, Component)]
+struct WarpWalkOut {
+    dir: FacingDir
+}
+
+// A warp tile in the world. Only doors actually show up.
+#[derive(Clone, Debug, Default, Component)]
+pub struct Warp {
+    pub kind: WarpKind
+}
+
+#[derive(Bundle, Default)]
+struct WarpBundle {
+    sprite_bundle: SpriteBundle,
+    texture_atlas: TextureAtlas,
+    warp: Warp,
+
+    grid_coords: GridCoords,
+    world_grid_coords_required: WorldGridCoordsRequired
+}
+
+impl LdtkEntity for WarpBundle {
+    fn bundle_entity(entity_instance: &EntityInstance,
+                     layer_instance: &LayerInstance,
+                     _tileset: Option<&Handle<Image>>,
+                     _tileset_definition: Option<&TilesetDefinition>,
+                     asset_server: &AssetServer,
+                     texture_atlases: &mut Assets<TextureAtlasLayout>) -> Self {
+        let kind = entity_instance.get_string_field("Kind").ok()
+            .and_then(|kind| WarpKind::from_name(kind))
+            .unwrap_or_default();
+        let layout = TextureAtlasLayout::from_grid(UVec2::splat(16), DOOR_FRAMES as u32, 1, None, None);
+
+        WarpBundle {
+            sprite_bundle: SpriteBundle {
+                texture: asset_server.load(DOOR_PATH),
+                visibility: if kind == WarpKind::Door { Visibility::Inherited } else { Visibility::Hidden },
+                ..default()
+            },
+            texture_atlas: TextureAtlas {
+                layout: texture_atlases.add(layout),
+                index: DOOR_CLOSED_FRAME
+            },
+            warp: Warp { kind },
+            grid_coords: GridCoords::from_entity_info(entity_instance, layer_instance),
+            ..Default::default()
+        }
+    }
 }
 
 // Keep a resource that has all the locations and handy stuff for figuring out if where warps go to,
@@ -28,96 +142,284 @@ struct WarpPending {
 #[derive(Default, Debug, Resource)]
 struct WarpCache {
     warp_tiles: HashMap<WorldGridCoords, WarpTarget>,
-    warp_targets: HashMap<EntityIid, WorldGridCoords>
+    warp_targets: HashMap<EntityIid, WarpArrival>,
+    warp_target_levels: HashMap<EntityIid, LevelIid>,
+    warp_target_projects: HashMap<EntityIid, String>, // Which project each target is in.
+    level_links: Vec<(LevelIid, LevelIid)>, // Which levels have a warp to which other levels.
+    errors: Vec<WarpError> // Warps that can't be used, and why.
 }
 
-// For now just do this every frame, 
-fn build_warp_cache(mut warp_cache: ResMut<WarpCache>,
-                    ldtk_project_assets: Res<Assets<LdtkProject>>,
-                    ldtk_project_entities: Query<&Handle<LdtkProject>>) {
-
-    // Get the ldtk project data.
-    let ldtk_project = ldtk_project_assets.get(ldtk_project_entities.single()).expect("ldtk project should be loaded before track_level system runs.");
-
-    // The warp tiles and targets should be stored in the table of contents, so we can get 
-    // all of the in the entire world before any levels are loaded.
-    // Cache this data in our own resource so we can access it easily.
-    for entry in &ldtk_project.json_data().toc {
-        if entry.identifier == "Warp" {
-            for instance in &entry.instances_data {
-
-                // Get the z coord from the level it belongs to.
-                let mut z = 0;
-                for level in &ldtk_project.json_data().levels {
-                    if level.iid == instance.iids.level_iid {
-                        z = level.world_depth;
+// Something wrong with a warp in the LDtk project.
+#[derive(Clone, Debug, Error)]
+pub enum WarpError {
+    #[error("Warp {warp} doesn't have a Target")]
+    MissingTarget { warp: String },
+    #[error("Warp {warp} points at {target}, which isn't a WarpTarget")]
+    UnknownTarget { warp: String, target: String },
+    #[error("Warp {warp} points at a target in {project}, which isn't one of the world's projects")]
+    UnknownProject { warp: String, project: String },
+    #[error("Warp {warp} points at {target} in {project}, but that target is in {actual}")]
+    WrongProject { warp: String, target: String, project: String, actual: String },
+    #[error("Warp {warp} has a bad RequiresFlag condition: {error}")]
+    BadCondition { warp: String, error: FlagConditionParseError },
+    #[error("Warp {warp} has an unknown {field} {value:?}")]
+    UnknownValue { warp: String, field: &'static str, value: String }
+}
+
+// Which grid each layer is on, so entities on any layer can be split up into tiles.
+fn layer_grid_sizes(ldtk_json: &LdtkJson) -> HashMap<String, i32> {
+    let mut grid_sizes = HashMap::new();
+    for level in &ldtk_json.levels {
+        for layer_instance in level.layer_instances.iter().flatten() {
+            if let Some(layer_definition) = ldtk_json.defs.layers.iter().find(|layer_definition| layer_definition.uid == layer_instance.layer_def_uid) {
+                grid_sizes.insert(layer_instance.iid.clone(), layer_definition.grid_size);
+            }
+        }
+    }
+    grid_sizes
+}
+
+// Every tile an entity in the table of contents covers.
+// Each cell of the entity's layer grid counts as the tile under the middle of it.
+fn toc_instance_tiles(instance: &LdtkTocInstanceData, grid_size: i32, z: i32) -> Vec<WorldGridCoords> {
+    let columns = (instance.wid_px / grid_size).max(1);
+    let rows = (instance.hei_px / grid_size).max(1);
+
+    let mut tiles = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let pixel_x = instance.world_x + column * grid_size + grid_size / 2;
+            let pixel_y = instance.world_y + row * grid_size + grid_size / 2;
+
+            // LDtk's y goes down, ours goes up.
+            let tile = WorldGridCoords {
+                x: pixel_x.div_euclid(TILE_GRID_SIZE.x),
+                y: -pixel_y.div_euclid(TILE_GRID_SIZE.y) - 1,
+                z
+            };
+            if !tiles.contains(&tile) {
+                tiles.push(tile);
+            }
+        }
+    }
+    tiles
+}
+
+// A project to look for warps in, and the depth offset its levels get. (see world_data::WorldProject)
+#[derive(Clone, Copy)]
+pub struct WarpProject<'a> {
+    pub path: &'a str,
+    pub depth_offset: i32,
+    pub ldtk_json: &'a LdtkJson
+}
+
+impl WarpCache {
+    // All the warp tiles and targets are stored in the table of contents, so we can get
+    // all of them in the entire world before any levels are loaded.
+    // Warps can go to targets in any of the projects, so they're all done together.
+    fn build(projects: &[WarpProject]) -> Self {
+        let mut warp_cache = WarpCache::default();
+
+        // Every warp and the tiles it covers, to hook up to their targets once we've seen every target in every project.
+        let mut warps: Vec<(String, LevelIid, Vec<WorldGridCoords>, WarpTarget)> = Vec::new();
+
+        for project in projects {
+            let ldtk_json = project.ldtk_json;
+            let grid_sizes = layer_grid_sizes(ldtk_json);
+
+            // Get the z coord from the level it belongs to.
+            let level_depth = |level_iid: &str| ldtk_json.levels.iter()
+                .find(|level| level.iid == level_iid)
+                .map_or(0, |level| level.world_depth) + project.depth_offset;
+            let grid_size = |instance: &LdtkTocInstanceData| grid_sizes.get(&instance.iids.layer_iid).copied().unwrap_or(TILE_GRID_SIZE.x);
+
+            for entry in &ldtk_json.toc {
+                if entry.identifier == "Warp" {
+                    for instance in &entry.instances_data {
+                        let warp = instance.iids.entity_iid.clone();
+                        let fields = match &instance.fields {
+                            Some(serde_json::Value::Object(fields)) => Some(fields),
+                            _ => None
+                        };
+
+                        // Get the target this one points to.
+                        // Targets in the same project are an entity reference, ones in another project
+                        // have to be given by hand, as the project's path and the target's iid.
+                        let target_project = match fields.and_then(|fields| fields.get("TargetProject")) {
+                            Some(serde_json::Value::String(target_project)) => target_project.clone(),
+                            _ => project.path.to_string()
+                        };
+                        let entity_iid = match (fields.and_then(|fields| fields.get("Target")), fields.and_then(|fields| fields.get("TargetIid"))) {
+                            (Some(serde_json::Value::Object(target)), _) => match target.get("entityIid") {
+                                Some(serde_json::Value::String(entity_iid)) => entity_iid.clone(),
+                                _ => {
+                                    warp_cache.errors.push(WarpError::MissingTarget { warp });
+                                    continue;
+                                }
+                            },
+                            (_, Some(serde_json::Value::String(entity_iid))) => entity_iid.clone(),
+                            _ => {
+                                warp_cache.errors.push(WarpError::MissingTarget { warp });
+                                continue;
+                            }
+                        };
+
+                        // Any condition on using the warp.
+                        let requires = match fields.and_then(|fields| fields.get("RequiresFlag")) {
+                            Some(serde_json::Value::String(condition)) => match FlagCondition::parse(condition) {
+                                Ok(condition) => Some(condition),
+                                Err(error) => {
+                                    warp_cache.errors.push(WarpError::BadCondition { warp: warp.clone(), error });
+                                    None
+                                }
+                            },
+                            _ => None
+                        };
+
+                        // How it's used. Step on, unless it says otherwise.
+                        let kind = match fields.and_then(|fields| fields.get("Kind")) {
+                            Some(serde_json::Value::String(kind)) => WarpKind::from_name(kind).unwrap_or_else(|| {
+                                warp_cache.errors.push(WarpError::UnknownValue { warp: warp.clone(), field: "Kind", value: kind.clone() });
+                                WarpKind::StepOn
+                            }),
+                            _ => WarpKind::StepOn
+                        };
+
+                        let transition = match fields.and_then(|fields| fields.get("Transition")) {
+                            Some(serde_json::Value::String(transition)) => TransitionStyle::from_name(transition).unwrap_or_else(|| {
+                                warp_cache.errors.push(WarpError::UnknownValue { warp: warp.clone(), field: "Transition", value: transition.clone() });
+                                TransitionStyle::FadeBlack
+                            }),
+                            _ => TransitionStyle::FadeBlack
+                        };
+
+                        // Every tile the warp covers works the same.
+                        // The level gets filled in once we know where the target is.
+                        let warp_target = WarpTarget {
+                            level_iid: LevelIid::default(),
+                            entity_iid: EntityIid::new(entity_iid),
+                            project: target_project,
+                            requires,
+                            kind,
+                            transition
+                        };
+                        let tiles = toc_instance_tiles(instance, grid_size(instance), level_depth(&instance.iids.level_iid));
+                        warps.push((warp, LevelIid::new(instance.iids.level_iid.clone()), tiles, warp_target));
                     }
                 }
 
-                // Convert the world position of the instance to worldgridcoords.
-                let world_grid_coords = WorldGridCoords {
-                    x: (instance.world_x + instance.wid_px/2) / TILE_GRID_SIZE.x,
-                    y: -(instance.world_y + instance.hei_px/2) / TILE_GRID_SIZE.y,
-                    z: z
-                };
-
-                // Get the target this one points to.
-                if let Some(serde_json::Value::Object(fields)) = &instance.fields {
-                    if let Some(serde_json::Value::Object(target)) = fields.get("Target") {
-
-                        // Entity iid.
-                        let entity_iid = if let Some(serde_json::Value::String(entity_iid)) = target.get("entityIid") {
-                            Some(EntityIid::new(entity_iid.clone()))
-                        } else {
-                            None
+                if entry.identifier == "WarpTarget" {
+                    for instance in &entry.instances_data {
+                        // Which way to face when we get there, and whether to walk out.
+                        let fields = match &instance.fields {
+                            Some(serde_json::Value::Object(fields)) => Some(fields),
+                            _ => None
+                        };
+                        let facing = match fields.and_then(|fields| fields.get("Facing")) {
+                            Some(serde_json::Value::String(facing)) => match facing.as_str() {
+                                "Up" => Some(FacingDir::Up),
+                                "Down" => Some(FacingDir::Down),
+                                "Left" => Some(FacingDir::Left),
+                                "Right" => Some(FacingDir::Right),
+                                _ => None
+                            },
+                            _ => None
                         };
+                        let walk_out = matches!(fields.and_then(|fields| fields.get("WalkOut")), Some(serde_json::Value::Bool(true)));
 
-                        // Level iid
-                        let level_iid = if let Some(serde_json::Value::String(level_iid)) = target.get("levelIid") {
-                            Some(LevelIid::new(level_iid.clone()))
-                        } else {
-                            None
+                        // Targets are a single tile, if it's any bigger we arrive in its top left corner.
+                        let Some(position) = toc_instance_tiles(instance, grid_size(instance), level_depth(&instance.iids.level_iid)).first().copied() else {
+                            continue;
                         };
 
-                        // If we have both... add the item to our cache.
-                        if let (Some(entity_iid), Some(level_iid)) = (entity_iid, level_iid) {
-                            warp_cache.warp_tiles.insert(world_grid_coords, WarpTarget {
-                                entity_iid,
-                                level_iid
-                            });
-                        }
+                        warp_cache.warp_targets.insert(EntityIid::new(instance.iids.entity_iid.clone()), WarpArrival {
+                            position,
+                            facing,
+                            walk_out
+                        });
+                        warp_cache.warp_target_levels.insert(EntityIid::new(instance.iids.entity_iid.clone()), LevelIid::new(instance.iids.level_iid.clone()));
+                        warp_cache.warp_target_projects.insert(EntityIid::new(instance.iids.entity_iid.clone()), project.path.to_string());
                     }
                 }
             }
         }
 
-        if entry.identifier == "WarpTarget" {
-            for instance in &entry.instances_data {
-                
-                // Get the z coord from the level it belongs to.
-                let mut z = 0;
-                for level in &ldtk_project.json_data().levels {
-                    if level.iid == instance.iids.level_iid {
-                        z = level.world_depth;
-                    }
+        // Don't keep warps that go nowhere, or the player would be stuck waiting for them forever.
+        for (warp, from_level, tiles, mut warp_target) in warps {
+            let target = &warp_target.entity_iid;
+            let Some(target_project) = warp_cache.warp_target_projects.get(target) else {
+                if projects.iter().any(|project| project.path == warp_target.project) {
+                    warp_cache.errors.push(WarpError::UnknownTarget { warp, target: target.as_str().to_string() });
+                } else {
+                    warp_cache.errors.push(WarpError::UnknownProject { warp, project: warp_target.project });
                 }
+                continue;
+            };
+            if *target_project != warp_target.project {
+                warp_cache.errors.push(WarpError::WrongProject { warp, target: target.as_str().to_string(), project: warp_target.project, actual: target_project.clone() });
+                continue;
+            }
 
-                // Convert the world position of the instance to worldgridcoords.
-                let world_grid_coords = WorldGridCoords {
-                    x: (instance.world_x + instance.wid_px/2) / TILE_GRID_SIZE.x,
-                    y: -(instance.world_y + instance.hei_px/2) / TILE_GRID_SIZE.y,
-                    z: z
-                };
-
-                warp_cache.warp_targets.insert(EntityIid::new(instance.iids.entity_iid.clone()), world_grid_coords);
+            let Some(to_level) = warp_cache.warp_target_levels.get(target) else {
+                continue;
+            };
+            warp_target.level_iid = to_level.clone();
+            warp_cache.level_links.push((from_level, to_level.clone()));
+            for tile in tiles {
+                warp_cache.warp_tiles.insert(tile, warp_target.clone());
             }
         }
+
+        warp_cache
+    }
+}
+
+// Everything wrong with the warps in the projects.
+pub fn warp_errors(projects: &[WarpProject]) -> Vec<WarpError> {
+    WarpCache::build(projects).errors
+}
+
+// Which level every WarpTarget is in.
+pub fn warp_target_levels(projects: &[WarpProject]) -> HashMap<EntityIid, LevelIid> {
+    WarpCache::build(projects).warp_target_levels
+}
+
+// Every level a warp goes from, and the level it goes to. (which might be in another project)
+pub fn warp_level_links(projects: &[WarpProject]) -> Vec<(LevelIid, LevelIid)> {
+    WarpCache::build(projects).level_links
+}
+
+// Ask for the player to be warped to a WarpTarget without having to step on a warp tile.
+// (e.g. from a conversation)
+#[derive(Clone, Debug, Event)]
+pub struct WarpRequestEvent {
+    pub target_entity_iid: EntityIid,
+    pub transition: TransitionStyle
+}
+
+// The warps never move, so this only needs doing once.
+fn build_warp_cache(mut warp_cache: ResMut<WarpCache>,
+                    ldtk_projects: LdtkProjects) {
+
+    // Get the data from every project.
+    let projects = ldtk_projects.iter()
+        .map(|loaded_project| WarpProject {
+            path: loaded_project.path,
+            depth_offset: loaded_project.depth_offset,
+            ldtk_json: loaded_project.json_data()
+        })
+        .collect::<Vec<_>>();
+
+    *warp_cache = WarpCache::build(&projects);
+    for error in &warp_cache.errors {
+        println!("{}", error);
     }
 }
 
 // What happens when the player walks onto a warp tile?
 fn warp_player(mut commands: Commands,
                warp_cache: Res<WarpCache>,
+               flags: Res<GameFlags>,
                mut tile_moved_event_reader: EventReader<TileMovedEvent>,
                player_query: Query<(Entity, &Player, &WorldGridCoords), Without<WarpPending>>) 
 {   
@@ -127,36 +429,137 @@ fn warp_player(mut commands: Commands,
         // Find entity in our query. (only interested in potential player)
         if let Ok((player_entity, _, world_grid_coords)) = player_query.get(tile_moved_event.entity) {
 
-            // Did we step onto a warp tile?
-            if let Some(warp_target) = warp_cache.warp_tiles.get(world_grid_coords) {
-                println!("Attempting to warp player to new level {}", warp_target.level_iid);
+            // Did we step onto a warp tile? (doors and the like don't count, they're walked into instead)
+            if let Some(warp_target) = warp_cache.warp_tiles.get(world_grid_coords).filter(|warp_target| warp_target.kind == WarpKind::StepOn) {
+
+                // Locked.
+                if !warp_target.unlocked(&flags) {
+                    println!("The warp to {} is locked.", warp_target.level_iid);
+                    continue;
+                }
+
+                println!("Attempting to warp player to new level {} in {}", warp_target.level_iid, warp_target.project);
 
                 // Warp lock the player.
-                commands.entity(player_entity).insert(WarpPending {
-                    target: warp_target.clone(),
-                    fade_out_timer: Timer::new(Duration::from_secs_f32(WARP_FADE_OUT_TIME.as_secs_f32()), TimerMode::Once)
-                });
+                commands.entity(player_entity).insert(WarpPending::new(warp_target.clone(), None));
             }
         }
     }
 }
 
-// Slowly fade out the rect. Once we've faded out completely, actually warp the player.
+// Doors (and anything else that's walked into) can't be walked onto. That way someone walking straight at one
+// stops in front of it, rather than carrying on through, and walk_into_warps gets a chance to see them try.
+fn block_walk_into_warps(mut commands: Commands,
+                         warp_query: Query<(Entity, &Warp), Added<Warp>>) {
+    for (entity, warp) in &warp_query {
+        if warp.kind.is_walk_into() {
+            commands.entity(entity).insert(Blocking);
+        }
+    }
+}
+
+// Trying to walk into a door (or anything else that works like one) uses it.
+fn walk_into_warps(mut commands: Commands,
+                   warp_cache: Res<WarpCache>,
+                   flags: Res<GameFlags>,
+                   mut player_query: Query<(Entity, &WorldGridCoords, &mut TileMover), (With<Player>, Without<WarpPending>)>) {
+    for (player_entity, world_grid_coords, mut tile_mover) in &mut player_query {
+        if !tile_mover.is_stationary() || tile_mover.want_move_dir == MoveDir::NotMoving {
+            continue;
+        }
+
+        // What's on the tile we're about to walk into?
+        let move_dir_vec = movedir_to_vec(tile_mover.want_move_dir);
+        let next_grid_coords = WorldGridCoords { x: world_grid_coords.x + move_dir_vec.x, y: world_grid_coords.y + move_dir_vec.y, z: world_grid_coords.z };
+        let Some(warp_target) = warp_cache.warp_tiles.get(&next_grid_coords).filter(|warp_target| warp_target.kind.is_walk_into()) else {
+            continue;
+        };
+
+        // Stay put and face the door, whether it opens or not.
+        if let Some(facing_dir) = movedir_to_facingdir(tile_mover.want_move_dir) {
+            tile_mover.facing_dir = facing_dir;
+        }
+        tile_mover.want_move_dir = MoveDir::NotMoving;
+
+        if !warp_target.unlocked(&flags) {
+            println!("The warp to {} is locked.", warp_target.level_iid);
+            continue;
+        }
+
+        println!("Attempting to warp player to new level {} in {}", warp_target.level_iid, warp_target.project);
+
+        let door = if warp_target.kind == WarpKind::Door { Some(next_grid_coords) } else { None };
+        commands.entity(player_entity).insert(WarpPending::new(warp_target.clone(), door));
+    }
+}
+
+// Warp the player somewhere just because we were asked to.
+fn warp_request(mut commands: Commands,
+                warp_cache: Res<WarpCache>,
+                mut warp_request_event_reader: EventReader<WarpRequestEvent>,
+                player_query: Query<Entity, (With<Player>, Without<WarpPending>)>)
+{
+    for warp_request_event in warp_request_event_reader.read() {
+        if let Ok(player_entity) = player_query.get_single() {
+
+            // We need to know which level (and project) the target is in.
+            let target = &warp_request_event.target_entity_iid;
+            if let (Some(level_iid), Some(project)) = (warp_cache.warp_target_levels.get(target), warp_cache.warp_target_projects.get(target)) {
+                println!("Attempting to warp player to new level {} in {}", level_iid, project);
+
+                commands.entity(player_entity).insert(WarpPending::new(WarpTarget {
+                    level_iid: level_iid.clone(),
+                    entity_iid: target.clone(),
+                    project: project.clone(),
+                    requires: None,
+                    kind: WarpKind::StepOn,
+                    transition: warp_request_event.transition
+                }, None));
+            } else {
+                println!("Can't warp to {}, it isn't a WarpTarget!", warp_request_event.target_entity_iid.as_str());
+            }
+        }
+    }
+}
+
+// Start loading wherever we're warping to straight away, rather than once we get there,
+// so it's (hopefully) ready by the time the screen's covered.
+fn preload_warp_target(warp_pending_query: Query<&WarpPending, Added<WarpPending>>,
+                       mut preload_level_event_writer: EventWriter<PreloadLevelEvent>) {
+    for warp_pending in &warp_pending_query {
+        preload_level_event_writer.send(PreloadLevelEvent { level_iid: warp_pending.target.level_iid.clone() });
+    }
+}
+
+// Cover the screen up. Once it's completely covered, actually warp the player,
+// then reveal the screen again once the level we've warped to has loaded.
 fn warp_fade_out(time: Res<Time>, 
                  mut commands: Commands,
                  warp_cache: Res<WarpCache>,
-                 mut player_query: Query<(Entity, &mut WorldGridCoords, &mut WarpPending), With<Player>>,
-                 mut palette_settings: Query<&mut PaletteSwapPostProcessSettings>,
+                 mut screen_transition: ResMut<ScreenTransition>,
+                 mut player_query: Query<(Entity, &mut WorldGridCoords, &mut TileMover, &mut WarpPending), With<Player>>,
                  level_query: Query<&LevelIid>) {
 
-    if let Ok((entity, mut player_grid_coords, mut warp_locked)) = player_query.get_single_mut() {
-        // Reduce our timer.
-        warp_locked.fade_out_timer.tick(time.delta());
+    if let Ok((entity, mut player_grid_coords, mut tile_mover, mut warp_locked)) = player_query.get_single_mut() {
+        // Wait for the door to open first.
+        if let Some((_, door_timer)) = &mut warp_locked.door {
+            door_timer.tick(time.delta());
+            if !door_timer.finished() {
+                return;
+            }
+        }
 
-        // How dark do we need to be?
-        let darkness = (warp_locked.fade_out_timer.fraction() * 4.0) as i32;
+        if !warp_locked.fading {
+            screen_transition.fade_out(warp_locked.target.transition, WARP_FADE_OUT_TIME);
+            warp_locked.fading = true;
+        }
+
+        if !warp_locked.warped {
+            if !screen_transition.is_covered() {
+                return;
+            }
+            warp_locked.warped = true;
 
-        if warp_locked.fade_out_timer.just_finished() {
             // Load the target level.
             //*level_select = LevelSelection::Iid(warp_locked.target.level_iid.clone());
 
@@ -165,7 +568,8 @@ fn warp_fade_out(time: Res<Time>,
             // So we can warp right now rather than having to wait for the new level to load.
 
             // Try and find the entity that we are warping to.
-            if let Some(target_grid_coord) = warp_cache.warp_targets.get(&warp_locked.target.entity_iid) {
+            if let Some(arrival) = warp_cache.warp_targets.get(&warp_locked.target.entity_iid) {
+                let target_grid_coord = &arrival.position;
                 println!("world coords for warp target: {}, {}, {}", target_grid_coord.x, target_grid_coord.y, target_grid_coord.z);
 
                 // WARPING!
@@ -174,31 +578,57 @@ fn warp_fade_out(time: Res<Time>,
                 player_grid_coords.z = target_grid_coord.z;
 
                 println!("Found warp tile, warped to ({}, {}, {})", player_grid_coords.x, player_grid_coords.y, player_grid_coords.z);
+
+                if let Some(facing) = arrival.facing {
+                    tile_mover.facing_dir = facing;
+                }
             }
         }
 
-        // Set the darkness level
-        if !warp_locked.fade_out_timer.finished() {
-            for mut settings in &mut palette_settings {
-                settings.darkness = darkness;
+        // We might be waiting for the level we're warping to, to load. (it started loading when the warp did)
+        // So let's check if it's loaded, and if it is then we can show it.
+        if !level_query.iter().any(|level_iid| *level_iid == warp_locked.target.level_iid) {
+            // Still going, let them know if it's taking a while.
+            screen_transition.start_loading();
+        } else {
+
+            // Okay it's loaded. Remove the pending warp component and fade back in.
+            commands.entity(entity).remove::<WarpPending>();
+            screen_transition.fade_in(WARP_FADE_IN_TIME);
+
+            // Step out of the door.
+            if let Some(WarpArrival { facing: Some(dir), walk_out: true, .. }) = warp_cache.warp_targets.get(&warp_locked.target.entity_iid) {
+                commands.entity(entity).insert(WarpWalkOut { dir: *dir });
             }
         }
+    }
+}
+
+// Take the step out once we're able to move again.
+// This runs after the player's own movement, so it isn't overwritten by nothing being pressed.
+fn warp_walk_out(mut commands: Commands,
+                 mut player_query: Query<(Entity, &WarpWalkOut, &mut TileMover), Without<WarpPending>>) {
+    for (entity, walk_out, mut tile_mover) in &mut player_query {
+        if tile_mover.is_stationary() {
+            tile_mover.want_move_dir = facingdir_to_movedir(walk_out.dir);
+            commands.entity(entity).remove::<WarpWalkOut>();
+        }
+    }
+}
 
-        // If the timer is finished, we might be waiting for the level we're warping to, to load. 
-        // So let's check if it's loaded, and if it is then we can reset the darkness.
-        if warp_locked.fade_out_timer.finished() {
+// Swing doors open while they're being walked through.
+fn door_frames(player_query: Query<&WarpPending>,
+               mut door_query: Query<(&Warp, &WorldGridCoords, &mut TextureAtlas)>) {
+    for (warp, world_grid_coords, mut texture_atlas) in &mut door_query {
+        if warp.kind != WarpKind::Door {
+            continue;
+        }
 
-            // Check if the target level is loaded.
-            for level_iid in &level_query {
-                if *level_iid == warp_locked.target.level_iid {
-                    
-                    // Okay it's loaded. Remove the pending warp component and reset our darkness.
-                    commands.entity(entity).remove::<WarpPending>();
-                    for mut settings in &mut palette_settings {
-                        settings.darkness = 0;
-                    }
-                }
-            }
+        let frame = player_query.iter()
+            .find_map(|warp_pending| warp_pending.door.as_ref().filter(|(door, _)| door == world_grid_coords))
+            .map_or(DOOR_CLOSED_FRAME, |(_, door_timer)| (door_timer.fraction() * (DOOR_FRAMES - 1) as f32).round() as usize);
+        if texture_atlas.index != frame {
+            texture_atlas.index = frame;
         }
     }
 }
@@ -213,7 +643,15 @@ impl Plugin for WarpPlugin {
         app.init_resource::<WarpCache>();
         app.add_systems(FixedUpdate, build_warp_cache.run_if(run_if_ldtk_project_resource_available).run_if(run_once()));
 
+        // Doors (and the other warps) show up in the world.
+        app.register_ldtk_entity::<WarpBundle>("Warp");
+
         // Handle walking onto tiles and actually warping to new locations.
-        app.add_systems(FixedUpdate, (warp_player, warp_fade_out));
+        app.add_event::<WarpRequestEvent>();
+        app.add_systems(FixedUpdate, (warp_player, warp_request, preload_warp_target, warp_fade_out));
+        app.add_systems(FixedUpdate, (walk_into_warps, warp_walk_out).after(character::move_player));
+        // Before the cache of blocked tiles first sees the warp.
+        app.add_systems(FixedUpdate, block_walk_into_warps.before(collision::world_grid_coords_required));
+        app.add_systems(Update, door_frames);
     }
 }
\ No newline at end of file
@@ -0,0 +1,184 @@
+// The world data (the LDtk projects), and problems with it.
+// The world can be split over several projects, each loaded into its own world entity with its own level set.
+// A malformed level shouldn't take the whole game down with it, so anything reading the projects
+// reports what's wrong here and carries on with a sensible default instead.
+
+use std::collections::HashSet;
+
+use bevy::{app::{App, Plugin, Update}, asset::{Assets, Handle}, color::Color, ecs::system::SystemParam, prelude::{Commands, Component, Entity, Query, Res, ResMut, Resource}};
+use bevy_ecs_ldtk::{assets::{LdtkProject, LevelMetadataAccessor}, ldtk::{LdtkJson, Level}, ldtk::ldtk_fields::LdtkFieldsError, prelude::LdtkFields, EntityInstance};
+use thiserror::Error;
+
+use crate::flags::{FlagCondition, FlagConditionParseError};
+
+// The LDtk projects the game world is split up into. (the overworld, dungeons, interiors, etc)
+// Paths are relative to the assets folder.
+pub const WORLD_PROJECTS: [&str; 1] = ["world.ldtk"];
+
+// Each project's levels get their world depth shifted by this much for every project before it,
+// so levels from different projects never end up on the same layer as each other.
+// That does mean every project has to keep its depths between -500 and 499.
+pub const PROJECT_DEPTH_SPACING: i32 = 1000;
+
+// Which project a world entity (the one with the LdtkWorldBundle) is.
+#[derive(Clone, Debug, Component)]
+pub struct WorldProject {
+    pub path: String,
+    pub depth_offset: i32
+}
+
+impl WorldProject {
+    // The project at this index in WORLD_PROJECTS.
+    pub fn new(index: usize, path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            depth_offset: index as i32 * PROJECT_DEPTH_SPACING
+        }
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq, Hash)]
+pub enum WorldDataError {
+    #[error("There's no level {level_iid} in any of the LDtk projects")]
+    MissingLevel { level_iid: String },
+    #[error("{owner} doesn't have a {field} field")]
+    MissingField { owner: String, field: String },
+    #[error("{owner}'s {field} field is the wrong type")]
+    BadFieldType { owner: String, field: String },
+    #[error("{level}'s Palette has {count} colours, it needs at least 4")]
+    MissingPalette { level: String, count: usize },
+    #[error("{owner}'s {field} condition is bad: {error}")]
+    BadCondition { owner: String, field: String, error: FlagConditionParseError }
+}
+
+impl WorldDataError {
+    // Turn an error from reading an LDtk field into one of ours.
+    pub fn from_field(owner: &str, error: LdtkFieldsError) -> Self {
+        match error {
+            LdtkFieldsError::WrongFieldType { identifier } => WorldDataError::BadFieldType { owner: owner.to_string(), field: identifier },
+            LdtkFieldsError::FieldNotFound { identifier }
+            | LdtkFieldsError::UnexpectedNull { identifier } => WorldDataError::MissingField { owner: owner.to_string(), field: identifier }
+        }
+    }
+}
+
+// Everything that's been reported so far, so the same problem doesn't get logged every frame.
+#[derive(Default, Resource)]
+pub struct WorldDataErrors {
+    reported: HashSet<WorldDataError>
+}
+
+impl WorldDataErrors {
+    pub fn report(&mut self, error: WorldDataError) {
+        if !self.reported.contains(&error) {
+            println!("World data problem: {}", error);
+            self.reported.insert(error);
+        }
+    }
+}
+
+// Problems found while an entity was being spawned from LDtk.
+// Bundles can't get at resources, so they're left on the entity to be reported once it exists.
+#[derive(Clone, Debug, Default, Component)]
+pub struct WorldDataProblems(pub Vec<WorldDataError>);
+
+fn report_entity_problems(mut commands: Commands,
+                          mut world_data_errors: ResMut<WorldDataErrors>,
+                          query: Query<(Entity, &WorldDataProblems)>) {
+    for (entity, problems) in &query {
+        for error in &problems.0 {
+            world_data_errors.report(error.clone());
+        }
+        commands.entity(entity).remove::<WorldDataProblems>();
+    }
+}
+
+// One of the projects, once it's loaded.
+#[derive(Clone, Copy)]
+pub struct LoadedProject<'a> {
+    pub entity: Entity, // The world entity, which has the project's LevelSet.
+    pub path: &'a str,
+    pub depth_offset: i32,
+    pub project: &'a LdtkProject
+}
+
+impl<'a> LoadedProject<'a> {
+    pub fn json_data(&self) -> &'a LdtkJson {
+        self.project.json_data()
+    }
+
+    // Which layer (WorldGridCoords z) a level from this project is on.
+    pub fn level_depth(&self, level: &Level) -> i32 {
+        level.world_depth + self.depth_offset
+    }
+}
+
+// Every LDtk project in the world, for systems that need to read level data.
+#[derive(SystemParam)]
+pub struct LdtkProjects<'w, 's> {
+    worlds: Query<'w, 's, (Entity, &'static Handle<LdtkProject>, Option<&'static WorldProject>)>,
+    assets: Res<'w, Assets<LdtkProject>>
+}
+
+impl<'w, 's> LdtkProjects<'w, 's> {
+    // The projects that have finished loading.
+    pub fn iter(&self) -> impl Iterator<Item = LoadedProject<'_>> {
+        self.worlds.iter().filter_map(|(entity, handle, world_project)| {
+            self.assets.get(handle).map(|project| LoadedProject {
+                entity,
+                path: world_project.map_or("", |world_project| world_project.path.as_str()),
+                depth_offset: world_project.map_or(0, |world_project| world_project.depth_offset),
+                project
+            })
+        })
+    }
+
+    // Whether every project has loaded. (and there's at least one)
+    pub fn all_loaded(&self) -> bool {
+        !self.worlds.is_empty() && self.worlds.iter().all(|(_, handle, _)| self.assets.contains(handle))
+    }
+
+    // A level, from whichever project it's in.
+    pub fn level(&self, level_iid: &str) -> Result<(LoadedProject<'_>, &Level), WorldDataError> {
+        self.iter()
+            .find_map(|loaded_project| loaded_project.project.get_raw_level_by_iid(&level_iid.to_string())
+                .map(|level| (loaded_project, level)))
+            .ok_or_else(|| WorldDataError::MissingLevel { level_iid: level_iid.to_string() })
+    }
+}
+
+// A level's 4 colours, lightest first.
+pub fn level_palette(level: &Level) -> Result<[Color; 4], WorldDataError> {
+    let colours = match level.get_colors_field("Palette") {
+        Ok(colours) => colours,
+        Err(LdtkFieldsError::FieldNotFound { .. }) => &[],
+        Err(error) => return Err(WorldDataError::from_field(&level.identifier, error))
+    };
+
+    // Any colours past the first four are ignored.
+    colours.get(..4)
+        .and_then(|colours| colours.try_into().ok())
+        .ok_or_else(|| WorldDataError::MissingPalette { level: level.identifier.clone(), count: colours.len() })
+}
+
+// A flag condition written in one of an entity's fields, like an Actor's VisibleIf.
+// No condition (the field is missing or null) is None.
+pub fn entity_condition(entity_instance: &EntityInstance, field: &str) -> Result<Option<FlagCondition>, WorldDataError> {
+    let Ok(condition) = entity_instance.get_string_field(field) else {
+        return Ok(None);
+    };
+
+    FlagCondition::parse(condition).map(Some).map_err(|error| WorldDataError::BadCondition {
+        owner: format!("{} {}", entity_instance.identifier, entity_instance.iid),
+        field: field.to_string(),
+        error
+    })
+}
+
+pub struct WorldDataPlugin;
+impl Plugin for WorldDataPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldDataErrors>();
+        app.add_systems(Update, report_entity_problems);
+    }
+}
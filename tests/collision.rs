@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+
+use bevy::{ecs::schedule::Schedule, prelude::*};
+use bevy_ecs_ldtk::{LevelEvent, LevelIid};
+use bevy_plat::collision::{self, BlockedTilesCache, Blocking, WorldGridCoords};
+
+const LEVEL_IID: &str = "blocked-tiles-level";
+
+// A few signs and chests dotted around the level.
+const BLOCKERS: [(i32, i32); 4] = [(1, 1), (4, 2), (4, 3), (7, 5)];
+
+// Just the cache systems, in the same order as the CollisionPlugin runs them.
+fn cache_world() -> (World, Schedule) {
+    let mut world = World::new();
+    world.init_resource::<BlockedTilesCache>();
+    world.init_resource::<Events<LevelEvent>>();
+
+    let mut schedule = Schedule::default();
+    schedule.add_systems((collision::evict_despawned_level_blocked_tiles,
+                          collision::remove_blocked_tiles,
+                          collision::add_blocked_tiles).chain());
+    (world, schedule)
+}
+
+// level -> layer -> tiles, like ldtk spawns them.
+fn spawn_level(world: &mut World) -> Entity {
+    world.spawn(LevelIid::new(LEVEL_IID)).with_children(|level| {
+        level.spawn_empty().with_children(|layer| {
+            for (x, y) in BLOCKERS {
+                layer.spawn((WorldGridCoords { x, y, z: 0 }, Blocking));
+            }
+        });
+    }).id()
+}
+
+fn run_tick(schedule: &mut Schedule, world: &mut World) {
+    schedule.run(world);
+    world.clear_trackers();
+    world.resource_mut::<Events<LevelEvent>>().update();
+}
+
+#[test]
+fn incremental_cache_matches_every_blocking_entity() {
+    let (mut world, mut schedule) = cache_world();
+    spawn_level(&mut world);
+    run_tick(&mut schedule, &mut world);
+
+    // One goes away and another turns up somewhere else.
+    let blocker = world.query_filtered::<Entity, With<Blocking>>().iter(&world).next().unwrap();
+    world.despawn(blocker);
+    world.spawn((WorldGridCoords { x: 9, y: 9, z: 0 }, Blocking));
+    run_tick(&mut schedule, &mut world);
+
+    let expected: HashSet<WorldGridCoords> = world.query_filtered::<&WorldGridCoords, With<Blocking>>().iter(&world).copied().collect();
+    assert_eq!(world.resource::<BlockedTilesCache>().blocked_tile_locations, expected);
+}
+
+#[test]
+fn despawned_level_is_evicted_from_the_cache() {
+    let (mut world, mut schedule) = cache_world();
+    let level = spawn_level(&mut world);
+
+    // Something that isn't part of the level, it should stay.
+    world.spawn((WorldGridCoords { x: -1, y: -1, z: 0 }, Blocking));
+    run_tick(&mut schedule, &mut world);
+    assert!(world.resource::<BlockedTilesCache>().len() > 1);
+
+    world.entity_mut(level).despawn_recursive();
+    world.send_event(LevelEvent::Despawned(LevelIid::new(LEVEL_IID)));
+    run_tick(&mut schedule, &mut world);
+
+    let blocked_tiles_cache = world.resource::<BlockedTilesCache>();
+    assert_eq!(blocked_tiles_cache.len(), 1);
+    assert_eq!(blocked_tiles_cache.blocked_tile_locations, HashSet::from([WorldGridCoords { x: -1, y: -1, z: 0 }]));
+}
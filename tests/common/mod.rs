@@ -0,0 +1,259 @@
+// Runs the game without a window or a GPU, so tests can walk the player around a small fixture world.
+// Build one with TestGame::new, hold some buttons, step FixedUpdate a few times and check where everything ended up.
+
+#![allow(dead_code)] // Not every test uses every helper.
+
+use std::{collections::HashSet, time::Duration};
+
+use bevy::{app::SubApp, asset::AssetPlugin, ecs::event::Event, hierarchy::HierarchyPlugin, prelude::*, render::{render_resource::Shader, RenderApp}, time::TimeUpdateStrategy, transform::TransformPlugin};
+use bevy_ecs_ldtk::{assets::LdtkProject, ldtk::LdtkJson, prelude::*};
+use bevy_ecs_tilemap::TilemapPlugin;
+use bevy_plat::{character::{self, FacingDir, Player, TileMover}, collision::{self, WorldGridCoords}, dialogue, dialogue_graph, flags, input::{Action, ActionInput, InputBindings}, interaction, items, level_loading::{self, CurrentLevel}, persistence, save, terrain, transition, warp, world_data::{self, WorldProject}};
+
+// Where the fixture worlds live, relative to the crate.
+const FIXTURES_PATH: &str = "tests/fixtures";
+pub const TEST_WORLD: &str = "test_world.ldtk";
+pub const TEST_INTERIOR: &str = "test_interior.ldtk"; // Only reachable by warping from TEST_WORLD.
+
+// Same as the game, every update runs FixedUpdate exactly once.
+const FIXED_TIMESTEP: Duration = Duration::from_nanos(16_666_667);
+
+// How many updates to wait for things to load before giving up.
+const MAX_LOADING_UPDATES: usize = 2000;
+
+// The buttons the test is holding down.
+#[derive(Default, Resource)]
+struct ScriptedInput {
+    held: HashSet<Action>
+}
+
+// Stands in for the input plugin, which would be reading the keyboard.
+fn scripted_action_input(scripted_input: Res<ScriptedInput>,
+                         bindings: Res<InputBindings>,
+                         time: Res<Time<Real>>,
+                         mut action_input: ResMut<ActionInput>) {
+    action_input.update(scripted_input.held.clone(), time.elapsed_seconds(), bindings.buffer_window);
+}
+
+// Every event of a type that's been sent since the test started recording them.
+#[derive(Resource)]
+struct RecordedEvents<E: Event + Clone> {
+    events: Vec<E>
+}
+
+fn record_events<E: Event + Clone>(mut event_reader: EventReader<E>, mut recorded: ResMut<RecordedEvents<E>>) {
+    recorded.events.extend(event_reader.read().cloned());
+}
+
+pub struct TestGame {
+    pub app: App
+}
+
+impl TestGame {
+    // Load a fixture world and wait until the player is standing in it.
+    pub fn new(world: &str) -> Self {
+        Self::with_projects(&[world])
+    }
+
+    // Same as new, but with the world split over several projects. The player starts in the first one.
+    pub fn with_projects(projects: &[&str]) -> Self {
+        let world = projects[0];
+        let mut app = App::new();
+
+        app.add_plugins(MinimalPlugins)
+            .add_plugins(AssetPlugin {
+                file_path: String::from(FIXTURES_PATH),
+                ..default()
+            })
+            .add_plugins((TransformPlugin, HierarchyPlugin))
+            .add_plugins(ImagePlugin::default_nearest());
+
+        // Things the ldtk and tilemap plugins expect the renderer and sprite plugins to have set up.
+        app.init_asset::<Shader>();
+        app.init_asset::<TextureAtlasLayout>();
+        app.init_resource::<ClearColor>();
+
+        // The tilemap plugin puts its render systems straight into the render app, so give it one to fill and then throw it away.
+        app.insert_sub_app(RenderApp, SubApp::new());
+        app.add_plugins(TilemapPlugin);
+        app.remove_sub_app(RenderApp);
+
+        app.add_plugins(LdtkPlugin)
+            .insert_resource(LdtkSettings {
+                int_grid_rendering: IntGridRendering::Invisible,
+                level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation { load_level_neighbors: false },
+                ..default()
+            });
+
+        // The game itself, or at least the parts that don't need a screen.
+        app.add_plugins(world_data::WorldDataPlugin)
+            .add_plugins(flags::FlagsPlugin)
+            .add_plugins(level_loading::LevelLoadingPlugin)
+            .add_plugins(collision::CollisionPlugin)
+            .add_plugins(terrain::TerrainPlugin)
+            .add_plugins(character::CharacterPlugin)
+            .add_plugins(interaction::InteractionPlugin)
+            .add_plugins(dialogue::DialoguePlugin)
+            .add_plugins(dialogue_graph::DialogueGraphPlugin)
+            .add_plugins(transition::TransitionPlugin)
+            .add_plugins(warp::WarpPlugin)
+            .add_plugins(persistence::PersistencePlugin)
+            .add_plugins(items::ItemsPlugin)
+            .add_plugins(save::SavePlugin);
+
+        // The save plugin's quick save keys, which nothing ever presses.
+        app.init_resource::<ButtonInput<KeyCode>>();
+
+        app.init_resource::<InputBindings>();
+        app.init_resource::<ActionInput>();
+        app.init_resource::<ScriptedInput>();
+        app.add_systems(PreUpdate, scripted_action_input);
+
+        // Time moves on by exactly one fixed step every update, no matter how long the update really took.
+        app.insert_resource(Time::<Fixed>::from_duration(FIXED_TIMESTEP));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FIXED_TIMESTEP));
+
+        for (index, project) in projects.iter().enumerate() {
+            let ldtk_handle = app.world().resource::<AssetServer>().load(project.to_string());
+            app.world_mut().spawn((
+                LdtkWorldBundle {
+                    ldtk_handle,
+                    level_set: LevelSet::default(),
+                    ..default()
+                },
+                WorldProject::new(index, project)
+            ));
+        }
+
+        let mut game = Self { app };
+
+        // The save plugin starts a new game wherever the Player was placed, once everything's loaded.
+        assert!(game.wait_for(|game| game.ldtk_jsons().len() == projects.len()), "{:?} never loaded", projects);
+        assert!(game.wait_for(|game| game.player_level().is_some()), "The player never turned up in {}", world);
+
+        // A freshly spawned mover counts as part way through a step until its timer first runs out.
+        assert!(game.wait_for(|game| game.player_stationary()), "The player never stood still");
+        game
+    }
+
+    // Keep updating (for real, so assets get a chance to load) until something is true.
+    fn wait_for(&mut self, condition: impl Fn(&mut Self) -> bool) -> bool {
+        for _ in 0..MAX_LOADING_UPDATES {
+            if condition(self) {
+                return true;
+            }
+            self.app.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        false
+    }
+
+    // Copies of the LDtk data of every project that's loaded, in the order they were given.
+    pub fn ldtk_jsons(&mut self) -> Vec<LdtkJson> {
+        let world = self.app.world_mut();
+        let mut projects = world.query::<(&WorldProject, &Handle<LdtkProject>)>().iter(world)
+            .map(|(world_project, handle)| (world_project.depth_offset, handle.clone()))
+            .collect::<Vec<_>>();
+        projects.sort_by_key(|(depth_offset, _)| *depth_offset);
+
+        let ldtk_project_assets = world.resource::<Assets<LdtkProject>>();
+        projects.iter()
+            .filter_map(|(_, handle)| ldtk_project_assets.get(handle).map(|ldtk_project| ldtk_project.json_data().clone()))
+            .collect()
+    }
+
+    // A copy of the first project's LDtk data, once it's loaded.
+    pub fn ldtk_json(&mut self) -> Option<LdtkJson> {
+        self.ldtk_jsons().into_iter().next()
+    }
+
+    // Run FixedUpdate this many times.
+    pub fn step(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.app.update();
+        }
+    }
+
+    // Step until something is true, or give up after max_steps. Returns whether it happened.
+    pub fn step_until(&mut self, max_steps: usize, condition: impl Fn(&mut Self) -> bool) -> bool {
+        for _ in 0..max_steps {
+            if condition(self) {
+                return true;
+            }
+            self.app.update();
+        }
+        condition(self)
+    }
+
+    pub fn hold(&mut self, action: Action) {
+        self.app.world_mut().resource_mut::<ScriptedInput>().held.insert(action);
+    }
+
+    pub fn release(&mut self, action: Action) {
+        self.app.world_mut().resource_mut::<ScriptedInput>().held.remove(&action);
+    }
+
+    pub fn release_all(&mut self) {
+        self.app.world_mut().resource_mut::<ScriptedInput>().held.clear();
+    }
+
+    // Press and let go straight away.
+    pub fn tap(&mut self, action: Action) {
+        self.hold(action);
+        self.step(1);
+        self.release(action);
+    }
+
+    pub fn player_entity(&mut self) -> Entity {
+        let world = self.app.world_mut();
+        world.query_filtered::<Entity, With<Player>>().single(world)
+    }
+
+    pub fn player_coords(&mut self) -> WorldGridCoords {
+        let world = self.app.world_mut();
+        *world.query_filtered::<&WorldGridCoords, With<Player>>().single(world)
+    }
+
+    pub fn player_stationary(&mut self) -> bool {
+        let world = self.app.world_mut();
+        world.query_filtered::<&TileMover, With<Player>>().single(world).is_stationary()
+    }
+
+    pub fn player_facing(&mut self) -> FacingDir {
+        let world = self.app.world_mut();
+        world.query_filtered::<&TileMover, With<Player>>().single(world).facing_dir()
+    }
+
+    // The level the player is in, once they've been placed in one.
+    pub fn player_level(&mut self) -> Option<LevelIid> {
+        let world = self.app.world_mut();
+        world.query_filtered::<&CurrentLevel, With<Player>>().iter(world).next()?.level_iid.clone()
+    }
+
+    // The iid of a level in any of the fixture's projects, by its name.
+    pub fn level_iid(&mut self, identifier: &str) -> LevelIid {
+        let ldtk_jsons = self.ldtk_jsons();
+        let level = ldtk_jsons.iter()
+            .flat_map(|ldtk_json| ldtk_json.levels.iter())
+            .find(|level| level.identifier == identifier)
+            .unwrap_or_else(|| panic!("No level called {} in the fixture", identifier));
+        LevelIid::new(level.iid.clone())
+    }
+
+    // Whether a level has been spawned.
+    pub fn level_loaded(&mut self, level_iid: &LevelIid) -> bool {
+        let world = self.app.world_mut();
+        let loaded = world.query::<&LevelIid>().iter(world).any(|loaded_level_iid| loaded_level_iid == level_iid);
+        loaded
+    }
+
+    // Start keeping every event of this type, to look at with events().
+    pub fn record_events<E: Event + Clone>(&mut self) {
+        self.app.insert_resource(RecordedEvents::<E> { events: Vec::new() });
+        self.app.add_systems(Last, record_events::<E>);
+    }
+
+    pub fn events<E: Event + Clone>(&self) -> &[E] {
+        &self.app.world().get_resource::<RecordedEvents<E>>().expect("Call record_events first").events
+    }
+}
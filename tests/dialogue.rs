@@ -0,0 +1,69 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_plat::{character::FacingDir, collision::WorldGridCoords, dialogue::OpenDialogueEvent, dialogue_graph::{Conversation, DialogueGraph}, flags::GameFlags, input::Action, interaction::InteractEvent};
+use common::{TestGame, TEST_WORLD};
+
+#[test]
+fn actors_with_only_flat_dialogue_can_be_talked_to() {
+    let mut game = TestGame::new(TEST_WORLD);
+    game.record_events::<OpenDialogueEvent>();
+
+    // The villager is one tile down and one to the right, so step down and face them.
+    game.hold(Action::Down);
+    assert!(game.step_until(120, |game| game.player_coords() == WorldGridCoords { x: 2, y: -4, z: 0 }));
+    game.release_all();
+    game.step(30);
+    game.hold(Action::Right);
+    game.step(30);
+    game.release_all();
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 2, y: -4, z: 0 }, "The villager should be in the way");
+
+    game.tap(Action::A);
+    game.step(5);
+    let opened = game.events::<OpenDialogueEvent>();
+    assert_eq!(opened.len(), 1, "Expected the villager's dialogue box to open");
+    assert_eq!(opened[0].text, vec![String::from("Oh, hello!")]);
+}
+
+// Start talking to something, the same as if the player had walked up to it and pressed A.
+fn talk_to(game: &mut TestGame, target: Entity) {
+    let interactor = game.player_entity();
+    game.app.world_mut().send_event(InteractEvent { interactor, target, dir: FacingDir::Down });
+    game.step(60); // Long enough for all of the text to show.
+}
+
+fn opened(game: &TestGame) -> Vec<(Vec<String>, Vec<String>)> {
+    game.events::<OpenDialogueEvent>().iter().map(|event| (event.text.clone(), event.choices.clone())).collect()
+}
+
+fn strings(strings: &[&str]) -> Vec<String> {
+    strings.iter().map(|string| string.to_string()).collect()
+}
+
+#[test]
+fn conversations_follow_choices_and_branches() {
+    let mut game = TestGame::new(TEST_WORLD);
+    game.record_events::<OpenDialogueEvent>();
+
+    // The box hangs off the camera.
+    game.app.world_mut().spawn((Camera2d, SpatialBundle::default()));
+    let graph = game.app.world().resource::<AssetServer>().load("conversation.dialogue.ron");
+    let tester = game.app.world_mut().spawn(Conversation { graph: graph.clone() }).id();
+    assert!(game.step_until(600, |game| game.app.world().resource::<Assets<DialogueGraph>>().contains(&graph)));
+
+    // The secret choice isn't offered without its flag.
+    talk_to(&mut game, tester);
+    assert_eq!(opened(&game), vec![(strings(&["Nice to meet you."]), strings(&["Hi", "Bye"]))]);
+    assert!(game.app.world().resource::<GameFlags>().get("met_tester"));
+
+    // Bye is the second choice shown, but the third one written down, and it ends the conversation.
+    game.tap(Action::Down);
+    game.tap(Action::A);
+    game.step(30);
+    assert_eq!(opened(&game).len(), 1, "Expected the conversation to be over, got {:?}", opened(&game));
+
+    // Having met, it branches somewhere else next time.
+    talk_to(&mut game, tester);
+    assert_eq!(opened(&game)[1], (strings(&["Back again?"]), Vec::new()));
+}
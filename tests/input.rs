@@ -0,0 +1,24 @@
+use bevy::input::keyboard::KeyCode;
+use bevy_plat::input::{Action, InputBindings};
+
+#[test]
+fn rebinding_replaces_every_key_for_the_action() {
+    let mut bindings = InputBindings::default();
+    bindings.keyboard.get_mut(&Action::Start).unwrap().push(KeyCode::Space);
+
+    assert_eq!(bindings.rebind_key(Action::Start, KeyCode::KeyP), Ok(()));
+    assert_eq!(bindings.keyboard[&Action::Start], vec![KeyCode::KeyP]);
+}
+
+#[test]
+fn a_key_used_by_another_action_is_refused() {
+    let mut bindings = InputBindings::default();
+
+    // Z is A's.
+    assert_eq!(bindings.rebind_key(Action::Start, KeyCode::KeyZ), Err(Action::A));
+    assert_eq!(bindings.keyboard[&Action::Start], vec![KeyCode::Enter]);
+    assert_eq!(bindings.keyboard[&Action::A], vec![KeyCode::KeyZ]);
+
+    // Rebinding an action to its own key is fine.
+    assert_eq!(bindings.rebind_key(Action::A, KeyCode::KeyZ), Ok(()));
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use bevy_plat::{input::Action, level_loading::CurrentLevelChangedEvent};
+use common::{TestGame, TEST_WORLD};
+
+#[test]
+fn neighbours_of_the_current_level_are_loaded() {
+    let mut game = TestGame::new(TEST_WORLD);
+    let east_level = game.level_iid("East");
+    let cellar_level = game.level_iid("Cellar");
+
+    assert!(game.step_until(60, |game| game.level_loaded(&east_level)));
+
+    // The cellar is underneath, not next door.
+    assert!(!game.level_loaded(&cellar_level));
+}
+
+#[test]
+fn walking_over_the_edge_changes_level() {
+    let mut game = TestGame::new(TEST_WORLD);
+    game.record_events::<CurrentLevelChangedEvent>();
+    let start_level = game.level_iid("Start");
+    let east_level = game.level_iid("East");
+
+    game.hold(Action::Right);
+    assert!(game.step_until(600, |game| game.player_level() == Some(east_level.clone())));
+    assert!(game.player_coords().x >= 10);
+
+    let changed = game.events::<CurrentLevelChangedEvent>().iter().any(|event| matches!(event,
+        CurrentLevelChangedEvent::Changed(_, Some(from), Some(to)) if *from == start_level && *to == east_level));
+    assert!(changed, "Expected a change from Start to East, got {:?}", game.events::<CurrentLevelChangedEvent>());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use bevy::math::IVec2;
+use bevy_plat::{character::TileMovedEvent, collision::WorldGridCoords, input::Action};
+use common::{TestGame, TEST_WORLD};
+
+#[test]
+fn player_starts_where_they_were_placed() {
+    let mut game = TestGame::new(TEST_WORLD);
+    let start_level = game.level_iid("Start");
+
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 2, y: -3, z: 0 });
+    assert_eq!(game.player_level(), Some(start_level));
+}
+
+#[test]
+fn holding_a_direction_walks_one_tile_at_a_time() {
+    let mut game = TestGame::new(TEST_WORLD);
+    game.record_events::<TileMovedEvent>();
+    let player = game.player_entity();
+
+    // Already facing down, so there's no turning on the spot first.
+    game.hold(Action::Down);
+    assert!(game.step_until(120, |game| game.player_coords() == WorldGridCoords { x: 2, y: -4, z: 0 }));
+    game.release_all();
+    game.step(60);
+
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 2, y: -4, z: 0 });
+    let moves: Vec<IVec2> = game.events::<TileMovedEvent>().iter()
+        .filter(|event| event.entity == player)
+        .map(|event| event.pos)
+        .collect();
+    assert!(moves.contains(&IVec2::new(2, -4)), "Expected a move to (2, -4), got {:?}", moves);
+}
+
+#[test]
+fn walls_block_movement() {
+    let mut game = TestGame::new(TEST_WORLD);
+
+    // There's a wall two tiles above the player, so they only get one step in.
+    game.hold(Action::Up);
+    game.step(240);
+
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 2, y: -2, z: 0 });
+}
+
+#[test]
+fn a_tap_early_in_a_step_takes_the_next_step() {
+    let mut game = TestGame::new(TEST_WORLD);
+
+    game.tap(Action::Down);
+    assert!(game.step_until(10, |game| game.player_coords() == WorldGridCoords { x: 2, y: -4, z: 0 }));
+
+    // Let go long before the first step is over, but it should still count once it is.
+    game.step(2);
+    game.tap(Action::Down);
+    game.step(60);
+
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 2, y: -5, z: 0 });
+}
+
+// Walk along the player's row until they get to this column.
+fn walk_right_to(game: &mut TestGame, x: i32) {
+    game.hold(Action::Right);
+    assert!(game.step_until(600, |game| game.player_coords().x == x));
+    game.release_all();
+    game.step(60);
+    assert_eq!(game.player_coords(), WorldGridCoords { x, y: -3, z: 0 });
+}
+
+#[test]
+fn ledges_are_hopped_over() {
+    let mut game = TestGame::new(TEST_WORLD);
+    walk_right_to(&mut game, 8);
+
+    // There's a ledge just below, so the player lands on the other side of it.
+    game.hold(Action::Down);
+    assert!(game.step_until(240, |game| game.player_coords().y == -5));
+    game.release_all();
+    game.step(60);
+
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 8, y: -5, z: 0 });
+}
+
+#[test]
+fn ledges_cant_be_landed_on() {
+    let mut game = TestGame::new(TEST_WORLD);
+    walk_right_to(&mut game, 6);
+
+    // Two ledges in a row below, the hop would land on the second one so it doesn't happen.
+    game.hold(Action::Down);
+    game.step(240);
+
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 6, y: -3, z: 0 });
+}
@@ -0,0 +1,24 @@
+mod common;
+
+use bevy_plat::{collision::WorldGridCoords, input::Action, terrain::{GrassEncounterEvent, GrassEncounters}};
+use common::{TestGame, TEST_WORLD};
+
+#[test]
+fn walking_into_grass_can_start_an_encounter() {
+    let mut game = TestGame::new(TEST_WORLD);
+    game.app.world_mut().resource_mut::<GrassEncounters>().chance = 1.0;
+    game.record_events::<GrassEncounterEvent>();
+    let player = game.player_entity();
+
+    // The grass is two tiles right of the player, the tile before it is plain floor.
+    game.hold(Action::Right);
+    assert!(game.step_until(240, |game| game.player_coords().x == 4));
+    game.release_all();
+    assert!(game.step_until(60, |game| !game.events::<GrassEncounterEvent>().is_empty()));
+    game.step(60);
+
+    let encounters = game.events::<GrassEncounterEvent>();
+    assert_eq!(encounters.len(), 1, "Expected one encounter, got {:?}", encounters);
+    assert_eq!(encounters[0].entity, player);
+    assert_eq!(encounters[0].world_grid_coords, WorldGridCoords { x: 4, y: -3, z: 0 });
+}
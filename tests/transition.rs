@@ -0,0 +1,42 @@
+mod common;
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_plat::transition::{LoadingIndicator, ScreenTransition, TransitionStyle};
+use common::{TestGame, TEST_WORLD};
+
+const FADE_DURATION: Duration = Duration::from_millis(100);
+
+fn loading_indicator_shown(game: &mut TestGame) -> bool {
+    let world = game.app.world_mut();
+    let shown = world.query_filtered::<(), With<LoadingIndicator>>().iter(world).next().is_some();
+    shown
+}
+
+#[test]
+fn loading_indicator_is_shown_while_loading_and_removed_after() {
+    let mut game = TestGame::new(TEST_WORLD);
+
+    // The indicator hangs off the camera, which the harness doesn't have.
+    game.app.world_mut().spawn(Camera2d);
+
+    game.app.world_mut().resource_mut::<ScreenTransition>().fade_out(TransitionStyle::FadeBlack, FADE_DURATION);
+    assert!(game.step_until(60, |game| game.app.world().resource::<ScreenTransition>().is_covered()));
+
+    // Covered but not loading, so nothing to show.
+    game.step(30);
+    assert!(!loading_indicator_shown(&mut game));
+
+    // It takes a moment to show up, so a quick load doesn't flash it up.
+    game.app.world_mut().resource_mut::<ScreenTransition>().start_loading();
+    game.step(2);
+    assert!(!loading_indicator_shown(&mut game));
+    assert!(game.step_until(60, loading_indicator_shown));
+
+    // And it goes away as soon as the screen starts fading back in.
+    game.app.world_mut().resource_mut::<ScreenTransition>().fade_in(FADE_DURATION);
+    assert!(game.step_until(2, |game| !loading_indicator_shown(game)));
+    assert!(game.step_until(60, |game| game.app.world().resource::<ScreenTransition>().is_clear()));
+    assert!(!loading_indicator_shown(&mut game));
+}
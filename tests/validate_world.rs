@@ -0,0 +1,32 @@
+use std::process::{Command, Output};
+
+use bevy_ecs_ldtk::ldtk::LdtkJson;
+use bevy_plat::world_data::level_palette;
+
+fn validate(path: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_validate_world")).arg(path).output().expect("validate_world should run")
+}
+
+#[test]
+fn an_empty_palette_is_reported() {
+    // The same as the test world, but East's palette has been emptied.
+    let output = validate("tests/fixtures/empty_palette.ldtk");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!output.status.success());
+    assert!(stdout.contains("East's Palette has 0 colours"), "Expected East's palette to be reported, got:\n{}", stdout);
+
+    // The world it was copied from has no palette problems.
+    let output = validate("tests/fixtures/test_world.ldtk");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Palette has"));
+}
+
+#[test]
+fn every_level_in_the_game_has_a_palette() {
+    let text = std::fs::read_to_string("assets/world.ldtk").unwrap();
+    let ldtk_json: LdtkJson = serde_json::from_str(&text).unwrap();
+
+    for level in &ldtk_json.levels {
+        assert!(level_palette(level).is_ok(), "{:?}", level_palette(level));
+    }
+}
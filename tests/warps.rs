@@ -0,0 +1,79 @@
+mod common;
+
+use bevy_plat::{character::FacingDir, collision::WorldGridCoords, input::Action, world_data::PROJECT_DEPTH_SPACING};
+use common::{TestGame, TEST_INTERIOR, TEST_WORLD};
+
+#[test]
+fn stepping_on_a_warp_moves_the_player_to_its_target() {
+    let mut game = TestGame::new(TEST_WORLD);
+    let cellar_level = game.level_iid("Cellar");
+
+    // The warp is three tiles below the player.
+    game.hold(Action::Down);
+    assert!(game.step_until(600, |game| game.player_coords().z == -1));
+    game.release_all();
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 4, y: -5, z: -1 });
+
+    // Then the cellar gets loaded around them.
+    assert!(game.step_until(600, |game| game.level_loaded(&cellar_level) && game.player_level() == Some(cellar_level.clone())));
+    game.step(60);
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 4, y: -5, z: -1 });
+}
+
+#[test]
+fn warps_can_go_to_another_project() {
+    let mut game = TestGame::with_projects(&[TEST_WORLD, TEST_INTERIOR]);
+    let start_level = game.level_iid("Start");
+    let interior_level = game.level_iid("Interior");
+
+    // The warp is two tiles left of the player, and its target is in the second project. (so its levels are 1000 deeper)
+    game.hold(Action::Left);
+    assert!(game.step_until(600, |game| game.player_coords().z == PROJECT_DEPTH_SPACING));
+    game.release_all();
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 3, y: -4, z: PROJECT_DEPTH_SPACING });
+
+    // The interior gets loaded by its own project's world, and the first project's levels go away.
+    assert!(game.step_until(600, |game| game.level_loaded(&interior_level) && game.player_level() == Some(interior_level.clone())));
+    assert!(game.step_until(60, |game| !game.level_loaded(&start_level)));
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 3, y: -4, z: PROJECT_DEPTH_SPACING });
+}
+
+#[test]
+fn the_target_level_starts_loading_as_soon_as_the_warp_does() {
+    let mut game = TestGame::new(TEST_WORLD);
+    let start_level = game.level_iid("Start");
+    let cellar_level = game.level_iid("Cellar");
+    assert!(!game.level_loaded(&cellar_level));
+
+    // The cellar should be there before the fade out has finished and the player's been moved.
+    game.hold(Action::Down);
+    assert!(game.step_until(600, |game| game.level_loaded(&cellar_level) || game.player_coords().z == -1));
+    assert_eq!(game.player_coords().z, 0, "The cellar only loaded once the player got there");
+    assert!(game.level_loaded(&start_level));
+}
+
+#[test]
+fn walking_into_a_door_goes_through_it() {
+    let mut game = TestGame::new(TEST_WORLD);
+    let cellar_level = game.level_iid("Cellar");
+
+    // Up a tile, then the door is four tiles right. Keep walking the whole way, never stopping in front of it.
+    game.hold(Action::Up);
+    assert!(game.step_until(120, |game| game.player_coords() == WorldGridCoords { x: 2, y: -2, z: 0 }));
+    game.release_all();
+    game.step(60);
+    game.hold(Action::Right);
+    assert!(game.step_until(600, |game| game.player_coords().z == -1));
+    game.release_all();
+
+    // The player never steps onto the door itself.
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 6, y: -7, z: -1 });
+    assert_eq!(game.player_facing(), FacingDir::Down);
+
+    // Then takes a step out of the doorway once they've arrived.
+    assert!(game.step_until(600, |game| game.player_level() == Some(cellar_level.clone())));
+    assert!(game.step_until(120, |game| game.player_coords() == WorldGridCoords { x: 6, y: -8, z: -1 }));
+    game.step(60);
+    assert_eq!(game.player_coords(), WorldGridCoords { x: 6, y: -8, z: -1 });
+    assert_eq!(game.player_facing(), FacingDir::Down);
+}